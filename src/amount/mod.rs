@@ -0,0 +1,136 @@
+#[cfg(test)]
+mod tests;
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::de;
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::Serialize;
+use serde::Serializer;
+
+pub(crate) const PRECISION: usize = 4;
+const SCALE: i64 = 10_000;
+const PARSE_ERROR: &str = "Oops, unable to parse this amount as a decimal with at most four fractional digits.";
+
+/// An exact, fixed-point decimal with four fractional digits.
+///
+/// Internally, the value is stored as an integer number of ten-thousandths,
+/// so arithmetic never accumulates any binary rounding error.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Amount(i64);
+
+impl Amount {
+    pub const ZERO: Self = Self(0);
+
+    /// Builds an amount out of a whole number of ten-thousandths.
+    pub fn from_units(units: i64) -> Self {
+        Self(units)
+    }
+
+    /// The amount as a whole number of ten-thousandths.
+    pub fn units(self) -> i64 {
+        self.0
+    }
+
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        self.0.checked_add(other.0).map(Self)
+    }
+
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        self.0.checked_sub(other.0).map(Self)
+    }
+
+    pub fn saturating_add(self, other: Self) -> Self {
+        Self(self.0.saturating_add(other.0))
+    }
+
+    pub fn saturating_sub(self, other: Self) -> Self {
+        Self(self.0.saturating_sub(other.0))
+    }
+}
+
+impl FromStr for Amount {
+    type Err = &'static str;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        let (negative, digits) = match src.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, src.strip_prefix('+').unwrap_or(src)),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        let is_numeric = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+        let valid = !(whole.is_empty() && fraction.is_empty())
+            && is_numeric(whole)
+            && is_numeric(fraction)
+            && fraction.len() <= PRECISION;
+        if !valid {
+            return Err(PARSE_ERROR);
+        };
+        let whole = match whole.is_empty() {
+            true => 0,
+            false => whole.parse::<i64>().map_err(|_| PARSE_ERROR)?,
+        };
+        let fraction = fraction
+            .bytes()
+            .chain(std::iter::repeat(b'0'))
+            .take(PRECISION)
+            .fold(0, |acc, digit| acc * 10 + i64::from(digit - b'0'));
+        let units = whole
+            .checked_mul(SCALE)
+            .and_then(|whole| whole.checked_add(fraction))
+            .ok_or(PARSE_ERROR)?;
+        match negative {
+            true => Ok(Self(-units)),
+            false => Ok(Self(units)),
+        }
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = match self.0 < 0 {
+            true => "-",
+            false => "",
+        };
+        let units = self.0.unsigned_abs();
+        let scale = SCALE.unsigned_abs();
+        write!(f, "{}{}.{:04}", sign, units / scale, units % scale)
+    }
+}
+
+impl Serialize for Amount {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct AmountVisitor;
+
+        impl<'de> de::Visitor<'de> for AmountVisitor {
+            type Value = Amount;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a decimal with at most four fractional digits")
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                value.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_str(AmountVisitor)
+    }
+}
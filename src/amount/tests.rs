@@ -0,0 +1,46 @@
+use super::Amount;
+
+#[test]
+fn parse() {
+    assert_eq!("1".parse::<Amount>(), Ok(Amount(10_000)));
+    assert_eq!("1.5".parse::<Amount>(), Ok(Amount(15_000)));
+    assert_eq!("0.0001".parse::<Amount>(), Ok(Amount(1)));
+    assert_eq!(".25".parse::<Amount>(), Ok(Amount(2_500)));
+    assert_eq!("2.".parse::<Amount>(), Ok(Amount(20_000)));
+    assert_eq!("-3.1415".parse::<Amount>(), Ok(Amount(-31_415)));
+}
+
+#[test]
+fn parse_invalid() {
+    assert!("".parse::<Amount>().is_err());
+    assert!(".".parse::<Amount>().is_err());
+    assert!("1.00001".parse::<Amount>().is_err());
+    assert!("1e5".parse::<Amount>().is_err());
+    assert!("NaN".parse::<Amount>().is_err());
+    assert!("inf".parse::<Amount>().is_err());
+    assert!("--1".parse::<Amount>().is_err());
+    assert!("99999999999999999999".parse::<Amount>().is_err());
+}
+
+#[test]
+fn display() {
+    assert_eq!(Amount(0).to_string(), "0.0000");
+    assert_eq!(Amount(15_000).to_string(), "1.5000");
+    assert_eq!(Amount(1).to_string(), "0.0001");
+    assert_eq!(Amount(-31_415).to_string(), "-3.1415");
+}
+
+#[test]
+fn exact_arithmetic() {
+    let tenth = "0.1".parse::<Amount>().unwrap();
+    let fifth = "0.2".parse::<Amount>().unwrap();
+    let sum = tenth.checked_add(fifth).unwrap();
+    assert_eq!(sum, "0.3".parse::<Amount>().unwrap());
+    assert_eq!(sum.to_string(), "0.3000");
+}
+
+#[test]
+fn checked_overflow() {
+    assert_eq!(Amount(i64::MAX).checked_add(Amount(1)), None);
+    assert_eq!(Amount(i64::MIN).checked_sub(Amount(1)), None);
+}
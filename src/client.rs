@@ -1,43 +1,102 @@
-use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
 
+use serde::Serialize;
+
+use crate::engine::core::DisputePolicy;
+use crate::error::EngineError;
 use crate::types::Amount;
 use crate::types::ClientId;
 use crate::types::EngineResult;
 
-const COMPARISON_ERROR: &'static str = "Unable to compare values.";
+/// The standing of an account.
+///
+/// An account is `Frozen` by a chargeback or by an administrator, and only an
+/// administrator can unfreeze it. A `Closed` account is final.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Active,
+    Frozen,
+    Closed,
+}
+
+impl Status {
+    /// Whether the account refuses every operation but administrative ones.
+    pub fn locked(self) -> bool {
+        self != Self::Active
+    }
+}
+
+/// A locked flag, as recorded before accounts could be closed.
+impl From<bool> for Status {
+    fn from(locked: bool) -> Self {
+        match locked {
+            true => Self::Frozen,
+            false => Self::Active,
+        }
+    }
+}
+
+impl FromStr for Status {
+    type Err = ();
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        match src {
+            "active" => Ok(Self::Active),
+            "frozen" => Ok(Self::Frozen),
+            "closed" => Ok(Self::Closed),
+            _ => Err(()),
+        }
+    }
+}
 
-#[derive(Clone, PartialEq)]
-#[cfg_attr(test, derive(Debug))]
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self {
+            Self::Active => "active",
+            Self::Frozen => "frozen",
+            Self::Closed => "closed",
+        };
+        f.write_str(status)
+    }
+}
+
+/// The account of a client.
+///
+/// Every method which updates a client is transactional: it computes all of
+/// the new balances (and status) first, and only assigns them once nothing
+/// can fail anymore, so that a refused update leaves the client untouched.
+#[derive(Clone, Debug, PartialEq)]
 pub struct Client {
     id: ClientId,
     available: Amount,
     held: Amount,
-    locked: bool,
+    status: Status,
 }
 
 impl Client {
-    #[cfg(not(test))]
     pub fn new(id: u16) -> Self {
         Self {
             id,
-            available: 0.0,
-            held: 0.0,
-            locked: false,
+            available: Amount::ZERO,
+            held: Amount::ZERO,
+            status: Status::Active,
         }
     }
 
-    #[cfg(test)]
-    pub fn new(
+    /// Rebuilds a client with the given balances (e.g., out of a snapshot).
+    pub(crate) fn with_balances(
         id: ClientId,
         available: Amount,
         held: Amount,
-        locked: bool,
+        status: Status,
     ) -> Self {
         Self {
             id,
             available,
             held,
-            locked,
+            status,
         }
     }
 
@@ -54,82 +113,210 @@ impl Client {
     }
 
     pub fn total(&self) -> Amount {
-        self.available + self.held
+        // `deposit` is the only operation which grows the total, and it refuses
+        // to do so past the representable range; saturation never occurs.
+        self.available.saturating_add(self.held)
+    }
+
+    pub fn status(&self) -> Status {
+        self.status
     }
 
     pub fn locked(&self) -> bool {
-        self.locked
+        self.status.locked()
     }
 
     pub fn deposit(&mut self, amount: Amount) -> EngineResult<()> {
         self.assert_not_locked()?;
-        self.available = self.available + amount;
+        self.total()
+            .checked_add(amount)
+            .ok_or_else(|| self.overflow())?;
+        let available = self
+            .available
+            .checked_add(amount)
+            .ok_or_else(|| self.overflow())?;
+        self.available = available;
         Ok(())
     }
 
     pub fn withdraw(&mut self, amount: Amount) -> EngineResult<()> {
         self.assert_not_locked()?;
-        let comparison = self
-            .available
-            .partial_cmp(&amount)
-            .ok_or_else(|| COMPARISON_ERROR)?;
-        match comparison {
-            Ordering::Less => Err("Oops, you cannot withdraw more money than what exists in your available funds."),
-            _ => {
-                self.available = self.available - amount;
+        match self.available < amount {
+            true => Err(EngineError::InsufficientFunds { client: self.id }),
+            false => {
+                let available = self
+                    .available
+                    .checked_sub(amount)
+                    .ok_or_else(|| self.overflow())?;
+                self.available = available;
                 Ok(())
             },
         }
     }
 
-    pub fn dispute(&mut self, amount: Amount) -> EngineResult<()> {
+    /// Disputes a deposit of `amount`, by moving the part of it which `policy`
+    /// holds out of the available funds and into the held ones; returns that
+    /// part.
+    pub fn dispute(
+        &mut self,
+        amount: Amount,
+        policy: DisputePolicy,
+    ) -> EngineResult<Amount> {
         self.assert_not_locked()?;
-        let comparison = self
+        let hold = policy
+            .hold(self.available, amount)
+            .ok_or(EngineError::InsufficientFunds { client: self.id })?;
+        let available = self
             .available
-            .partial_cmp(&amount)
-            .ok_or_else(|| COMPARISON_ERROR)?;
-        match comparison {
-            Ordering::Less => Err("Oops, you cannot dispute a transaction which deals with more money than what exists in your available funds."),
-            _ => {
-                self.available = self.available - amount;
-                self.held = self.held + amount;
-                Ok(())
-            },
-        }
+            .checked_sub(hold)
+            .ok_or_else(|| self.overflow())?;
+        let held =
+            self.held.checked_add(hold).ok_or_else(|| self.overflow())?;
+        self.available = available;
+        self.held = held;
+        Ok(hold)
     }
 
     pub fn resolve(&mut self, amount: Amount) -> EngineResult<()> {
         self.assert_not_locked()?;
-        let comparison = self
+        let held = self.release_held(amount)?;
+        let available = self
+            .available
+            .checked_add(amount)
+            .ok_or_else(|| self.overflow())?;
+        self.held = held;
+        self.available = available;
+        Ok(())
+    }
+
+    /// Charges back `amount` of a deposit, whose dispute holds `held`: the
+    /// held funds are released, and `amount` is removed out of the whole of
+    /// the funds (the available ones may then go below zero).
+    pub fn charge_back(
+        &mut self,
+        amount: Amount,
+        held: Amount,
+    ) -> EngineResult<()> {
+        self.assert_not_locked()?;
+        let available = amount
+            .checked_sub(held)
+            .and_then(|unheld| self.available.checked_sub(unheld))
+            .ok_or_else(|| self.overflow())?;
+        let held = self.release_held(held)?;
+        self.available = available;
+        self.held = held;
+        self.lock();
+        Ok(())
+    }
+
+    /// Disputes a withdrawal by provisionally crediting its amount into the
+    /// held funds, pending the outcome of the dispute.
+    pub fn dispute_withdrawal(&mut self, amount: Amount) -> EngineResult<()> {
+        self.assert_not_locked()?;
+        self.total()
+            .checked_add(amount)
+            .ok_or_else(|| self.overflow())?;
+        let held = self
             .held
-            .partial_cmp(&amount)
-            .ok_or_else(|| COMPARISON_ERROR)?;
-        match comparison {
-            Ordering::Less => Err("Oops, this dispute is not able to be resolved because you don't have enough money in your held funds."),
-            _ => {
-                self.held = self.held - amount;
-                self.available = self.available + amount;
-                Ok(())
-            },
-        }
+            .checked_add(amount)
+            .ok_or_else(|| self.overflow())?;
+        self.held = held;
+        Ok(())
     }
 
-    pub fn charge_back(&mut self, amount: Amount) -> EngineResult<()> {
+    /// Resolves a withdrawal dispute in favour of the withdrawal, which stands;
+    /// the provisional credit is removed from the held funds.
+    pub fn resolve_withdrawal(&mut self, amount: Amount) -> EngineResult<()> {
+        self.assert_not_locked()?;
+        self.held = self.release_held(amount)?;
+        Ok(())
+    }
+
+    /// Charges back `amount` of a withdrawal, whose dispute holds `held`: that
+    /// amount is reversed into the available funds, the rest of the provisional
+    /// credit is dropped, and the account is locked.
+    pub fn charge_back_withdrawal(
+        &mut self,
+        amount: Amount,
+        held: Amount,
+    ) -> EngineResult<()> {
         self.assert_not_locked()?;
-        self.resolve(amount)?;
-        self.withdraw(amount)?;
+        let available = self
+            .available
+            .checked_add(amount)
+            .ok_or_else(|| self.overflow())?;
+        let held = self.release_held(held)?;
+        self.available = available;
+        self.held = held;
         self.lock();
         Ok(())
     }
 
     pub fn lock(&mut self) {
-        self.locked = true;
+        self.status = Status::Frozen;
     }
 
-    fn assert_not_locked(&self) -> EngineResult<()> {
-        match self.locked {
-            true => Err("Oops, this account is locked and actions cannot be performed on it."),
-            false => Ok(()),
+    /// Freezes an active account, as an administrative action.
+    pub fn freeze(&mut self) -> EngineResult<()> {
+        self.assert_not_locked()?;
+        self.lock();
+        Ok(())
+    }
+
+    /// Unfreezes a frozen account (e.g., after a chargeback which turned out to
+    /// be a false positive).
+    pub fn unfreeze(&mut self) -> EngineResult<()> {
+        match self.status {
+            Status::Frozen => {
+                self.status = Status::Active;
+                Ok(())
+            },
+            Status::Active => {
+                Err(EngineError::AccountNotFrozen { client: self.id })
+            },
+            Status::Closed => {
+                Err(EngineError::AccountClosed { client: self.id })
+            },
         }
     }
+
+    /// Closes an account (whether active or frozen) which holds no funds.
+    pub fn close(&mut self) -> EngineResult<()> {
+        if self.status == Status::Closed {
+            return Err(EngineError::AccountClosed { client: self.id });
+        };
+        match self.total() == Amount::ZERO {
+            true => {
+                self.status = Status::Closed;
+                Ok(())
+            },
+            false => Err(EngineError::AccountNotEmpty { client: self.id }),
+        }
+    }
+
+    pub(crate) fn assert_not_locked(&self) -> EngineResult<()> {
+        match self.status {
+            Status::Active => Ok(()),
+            Status::Frozen => {
+                Err(EngineError::AccountLocked { client: self.id })
+            },
+            Status::Closed => {
+                Err(EngineError::AccountClosed { client: self.id })
+            },
+        }
+    }
+
+    /// The held funds which are left once `amount` of them is released.
+    fn release_held(&self, amount: Amount) -> EngineResult<Amount> {
+        match self.held < amount {
+            true => Err(EngineError::InsufficientHeldFunds { client: self.id }),
+            false => {
+                self.held.checked_sub(amount).ok_or_else(|| self.overflow())
+            },
+        }
+    }
+
+    fn overflow(&self) -> EngineError {
+        EngineError::BalanceOverflow { client: self.id }
+    }
 }
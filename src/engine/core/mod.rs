@@ -1,113 +1,346 @@
+mod policy;
+mod snapshot;
+pub mod store;
 #[cfg(test)]
 mod tests;
 mod utils;
 
 use std::collections::BTreeMap;
+use std::mem;
 
+pub use self::policy::DisputePolicy;
+pub use self::policy::Policy;
+use self::store::MemoryStore;
+use self::store::TransactionStore;
+use self::store::TransactionWrapper;
 use crate::client::Client;
+use crate::engine::journal::Delta;
+use crate::engine::journal::Deltas;
+use crate::engine::journal::Journal;
+use crate::transaction::AccountAction;
 use crate::transaction::Charge;
+use crate::transaction::ChargeKind;
 use crate::transaction::ChargeRef;
+use crate::transaction::DisputeState;
 use crate::transaction::Transaction;
+use crate::transaction::Transfer;
+use crate::types::Amount;
 use crate::types::ClientId;
 use crate::types::EngineResult;
 use crate::types::TransactionId;
 
-#[cfg_attr(test, derive(Debug))]
-struct TransactionWrapper {
-    transaction: Transaction,
-    disputed: bool,
-}
-
-impl TransactionWrapper {
-    fn new(transaction: Transaction) -> Self {
-        Self {
-            transaction,
-            disputed: false,
-        }
-    }
-}
-
-#[cfg_attr(test, derive(Debug))]
 pub struct Core {
     clients: BTreeMap<ClientId, Client>,
-    transactions: BTreeMap<TransactionId, TransactionWrapper>,
+    transactions: Box<dyn TransactionStore>,
+    policy: Policy,
+    journal: Option<Journal>,
 }
 
 impl Default for Core {
     fn default() -> Self {
+        Self::new(Policy::default())
+    }
+}
+
+impl Core {
+    pub fn new(policy: Policy) -> Self {
+        Self::with_store(policy, Box::<MemoryStore>::default())
+    }
+
+    pub fn with_store(
+        policy: Policy,
+        transactions: Box<dyn TransactionStore>,
+    ) -> Self {
         Self {
             clients: BTreeMap::default(),
-            transactions: BTreeMap::default(),
+            transactions,
+            policy,
+            journal: None,
         }
     }
-}
 
-impl Core {
+    /// Records every transaction accepted from now on into `journal`.
+    pub fn set_journal(&mut self, journal: Journal) {
+        self.journal = Some(journal);
+    }
+
     pub fn process(&mut self, transaction: Transaction) -> EngineResult<()> {
+        self.process_and_record(transaction)?
+    }
+
+    /// Processes `transaction`, returning its refusal, or else the failure to
+    /// record it onto the journal (in which case it was still applied).
+    pub(crate) fn process_and_record(
+        &mut self,
+        transaction: Transaction,
+    ) -> EngineResult<EngineResult<()>> {
+        match self.journal.clone() {
+            Some(journal) => {
+                let entry = transaction.clone();
+                let deltas = self.process_with_deltas(transaction)?;
+                Ok(journal.record(&entry, &deltas))
+            },
+            None => self.apply(transaction).map(Ok),
+        }
+    }
+
+    /// Processes `transaction`, returning the changes it made to its client
+    /// and, if it made or referred to a transfer, to the destination of that
+    /// transfer.
+    pub(crate) fn process_with_deltas(
+        &mut self,
+        transaction: Transaction,
+    ) -> EngineResult<Deltas> {
+        let client = transaction.client();
+        let destination = self.destination(&transaction)?;
+        let before = self.clients.get(&client).cloned();
+        let destination_before =
+            destination.and_then(|to| self.clients.get(&to).cloned());
+        self.apply(transaction)?;
+        let after = utils::get_client(&self.clients, &client)?;
+        let destination = match destination {
+            Some(to) => Some(Delta::between(
+                destination_before.as_ref(),
+                utils::get_client(&self.clients, &to)?,
+            )),
+            None => None,
+        };
+        Ok(Deltas {
+            client: Delta::between(before.as_ref(), after),
+            destination,
+        })
+    }
+
+    /// The destination of the transfer which `transaction` makes, or refers
+    /// to (if it is stored).
+    fn destination(
+        &self,
+        transaction: &Transaction,
+    ) -> EngineResult<Option<ClientId>> {
+        let referenced = match transaction.referenced_tx() {
+            Some(tx) => self.transactions.get(tx)?,
+            None => None,
+        };
+        let charge = match &referenced {
+            Some(transaction_wrapper) => transaction_wrapper.transaction(),
+            None => transaction,
+        };
+        match charge.as_charge() {
+            Some((ChargeKind::Transfer { to }, _)) => Ok(Some(to)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Flushes the journal, if there is one.
+    pub fn flush_journal(&self) -> EngineResult<()> {
+        match &self.journal {
+            Some(journal) => journal.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Applies `transaction` onto `self`, which is left untouched if it is
+    /// refused: every client is updated on a copy, and the copies are only
+    /// committed once the store (the last step which may fail) was written.
+    fn apply(&mut self, transaction: Transaction) -> EngineResult<()> {
         let Self {
             clients,
             transactions,
+            policy,
+            ..
         } = self;
         macro_rules! charge {
             ($action:ident @ [$client:ident, $tx:ident, $amount:ident]) => {{
-                utils::assert_transaction_doesnt_exists(transactions, $tx)?;
-                let client = utils::get_or_insert_client(clients, *$client);
+                utils::assert_transaction_doesnt_exists(&**transactions, $tx)?;
+                let mut client = utils::get_or_new_client(clients, *$client);
                 client.$action(*$amount)?;
+                (vec![client], None)
             }};
         }
         macro_rules! charge_ref {
-            ($action:ident @ $tx:ident, disputed := true) => {{
-                let TransactionWrapper {
-                    transaction: prev_transaction,
-                    disputed,
-                } = utils::get_transaction_wrapper(transactions, $tx)?;
-                if !*disputed {
-                    let Charge { client, amount, .. } =
-                        utils::as_deposit(prev_transaction)?;
-                    let client = utils::get_client(clients, client)?;
-                    client.$action(*amount)?;
-                    *disputed = true;
+            (
+                [$client:ident, $tx:ident, $amount:ident],
+                state := $next_state:ident
+            ) => {{
+                let mut transaction_wrapper =
+                    utils::get_transaction_wrapper(&**transactions, $tx)?;
+                let (kind, charge) = utils::as_disputable(
+                    transaction_wrapper.transaction(),
+                    $tx,
+                    policy,
+                )?;
+                utils::assert_client_matches(&charge.client, $client, $tx)?;
+                let next_state = DisputeState::$next_state;
+                let state = transaction_wrapper.state();
+                utils::assert_transition(state, next_state, $tx, policy)?;
+                let dispute = transaction_wrapper.dispute();
+                let amount = utils::settled_amount(
+                    charge, dispute, *$amount, next_state, $tx,
+                )?;
+                let (dispute, updated) = utils::settle(
+                    clients, kind, charge, dispute, amount, next_state, policy,
+                )?;
+                // A partial resolve leaves the rest of the charge disputed.
+                let next_state = match dispute.disputed == Amount::ZERO {
+                    true => next_state,
+                    false => DisputeState::Disputed,
                 };
+                transaction_wrapper.set_state(next_state);
+                transaction_wrapper.set_dispute(dispute);
+                (updated, Some((*$tx, transaction_wrapper)))
             }};
-            ($action:ident @ $tx:ident, disputed := false) => {{
-                let TransactionWrapper {
-                    transaction: prev_transaction,
-                    disputed,
-                } = utils::get_transaction_wrapper(transactions, $tx)?;
-                if *disputed {
-                    let Charge { client, amount, .. } =
-                        utils::as_deposit(prev_transaction)?;
-                    let client = utils::get_client(clients, client)?;
-                    client.$action(*amount)?;
-                    *disputed = false;
-                };
+        }
+        macro_rules! account_action {
+            ($action:ident @ $client:ident) => {{
+                let mut client = utils::get_client(clients, $client)?.clone();
+                client.$action()?;
+                (vec![client], None)
             }};
         }
-        match &transaction {
+        let (updated, settled) = match &transaction {
             Transaction::Deposit(Charge { client, tx, amount }) => {
                 charge!(deposit @ [client, tx, amount])
             },
             Transaction::Withdrawal(Charge { client, tx, amount }) => {
                 charge!(withdraw @ [client, tx, amount])
             },
-            Transaction::Dispute(ChargeRef { tx, .. }) => {
-                charge_ref!(dispute @ tx, disputed := true)
+            Transaction::Transfer(Transfer {
+                charge: Charge { client, tx, amount },
+                to,
+            }) => {
+                utils::assert_transaction_doesnt_exists(&**transactions, tx)?;
+                (utils::transfer(clients, *client, *to, *amount)?, None)
+            },
+            Transaction::Dispute(ChargeRef { client, tx, amount }) => {
+                charge_ref!([client, tx, amount], state := Disputed)
+            },
+            Transaction::Resolve(ChargeRef { client, tx, amount }) => {
+                charge_ref!([client, tx, amount], state := Resolved)
+            },
+            Transaction::Chargeback(ChargeRef { client, tx, amount }) => {
+                charge_ref!([client, tx, amount], state := ChargedBack)
+            },
+            Transaction::Freeze(AccountAction { client, .. }) => {
+                account_action!(freeze @ client)
             },
-            Transaction::Resolve(ChargeRef { tx, .. }) => {
-                charge_ref!(resolve @ tx, disputed := false)
+            Transaction::Unfreeze(AccountAction { client, .. }) => {
+                account_action!(unfreeze @ client)
             },
-            Transaction::Chargeback(ChargeRef { tx, .. }) => {
-                charge_ref!(charge_back @ tx, disputed := false)
+            Transaction::Close(AccountAction { client, .. }) => {
+                account_action!(close @ client)
             },
         };
-        transaction.charge_tx().map(|tx| {
-            let transaction_wrapper = TransactionWrapper::new(transaction);
-            transactions.insert(tx, transaction_wrapper);
-        });
+        let stored = match transaction.charge_tx() {
+            Some(tx) => Some((
+                tx,
+                TransactionWrapper::new(transaction, DisputeState::Processed),
+            )),
+            None => settled,
+        };
+        if let Some((tx, transaction_wrapper)) = stored {
+            transactions.insert(tx, transaction_wrapper)?;
+        };
+        for client in updated {
+            clients.insert(client.id(), client);
+        }
         Ok(())
     }
 
     pub fn clients(&self) -> Vec<&Client> {
         self.clients.values().collect()
     }
+
+    pub fn client(&self, client: ClientId) -> Option<&Client> {
+        self.clients.get(&client)
+    }
+
+    /// The stored charge with id `tx`, along with its dispute state.
+    pub fn transaction(
+        &self,
+        tx: TransactionId,
+    ) -> EngineResult<Option<TransactionWrapper>> {
+        self.transactions.get(tx)
+    }
+
+    pub fn policy(&self) -> &Policy {
+        &self.policy
+    }
+
+    /// Splits `self` into `shards` cores, moving each client (along with the
+    /// transactions it owns) into the core at index `shard_of(client)`.
+    ///
+    /// If this fails, `self` is left untouched.
+    pub(crate) fn split<F>(
+        &mut self,
+        shards: usize,
+        shard_of: F,
+    ) -> EngineResult<Vec<Self>>
+    where
+        F: Fn(ClientId) -> usize,
+    {
+        let mut cores = (0..shards)
+            .map(|_| {
+                let transactions = self.transactions.fork()?;
+                let mut core =
+                    Self::with_store(self.policy.clone(), transactions);
+                core.journal = self.journal.clone();
+                Ok(core)
+            })
+            .collect::<EngineResult<Vec<_>>>()?;
+        for entry in self.transactions.entries()? {
+            let (tx, transaction_wrapper) = entry?;
+            let client = transaction_wrapper.transaction().client();
+            cores[shard_of(client)]
+                .transactions
+                .insert(tx, transaction_wrapper)?;
+        }
+        self.transactions.clear()?;
+        for (id, client) in mem::take(&mut self.clients) {
+            cores[shard_of(id)].clients.insert(id, client);
+        }
+        Ok(cores)
+    }
+
+    /// Moves every client and transaction of `other` into `self`.
+    ///
+    /// The two cores are expected to be disjoint (e.g., as produced by
+    /// [`Core::split`]).
+    pub(crate) fn merge(&mut self, other: Self) -> EngineResult<()> {
+        for entry in other.transactions.entries()? {
+            let (tx, transaction_wrapper) = entry?;
+            self.transactions.insert(tx, transaction_wrapper)?;
+        }
+        self.clients.extend(other.clients);
+        Ok(())
+    }
+
+    /// Every stored transaction id, along with the client which owns it (and,
+    /// for a transfer, the client which it moved funds into).
+    pub(crate) fn owners(
+        &self,
+    ) -> EngineResult<BTreeMap<TransactionId, (ClientId, Option<ClientId>)>>
+    {
+        self.transactions
+            .entries()?
+            .map(|entry| {
+                let (tx, transaction_wrapper) = entry?;
+                let transaction = transaction_wrapper.transaction();
+                let to = match transaction.as_charge() {
+                    Some((ChargeKind::Transfer { to }, _)) => Some(to),
+                    _ => None,
+                };
+                Ok((tx, (transaction.client(), to)))
+            })
+            .collect()
+    }
+
+    /// Removes the client with id `client` (e.g., to lend it to another core).
+    pub(crate) fn take_client(&mut self, client: ClientId) -> Option<Client> {
+        self.clients.remove(&client)
+    }
+
+    /// Inserts `client`, replacing any client with the same id.
+    pub(crate) fn insert_client(&mut self, client: Client) {
+        self.clients.insert(client.id(), client);
+    }
 }
@@ -0,0 +1,85 @@
+use std::str::FromStr;
+
+use crate::transaction::ChargeKind;
+use crate::types::Amount;
+
+/// The rules according to which `Core` processes transactions.
+#[derive(Clone, Debug)]
+pub struct Policy {
+    /// Whether a transaction whose dispute was resolved may be disputed again.
+    pub allow_redispute: bool,
+    /// Whether deposits may be disputed.
+    pub dispute_deposits: bool,
+    /// Whether withdrawals may be disputed.
+    pub dispute_withdrawals: bool,
+    /// Whether transfers may be disputed.
+    pub dispute_transfers: bool,
+    /// What the dispute of a deposit (or of a transfer, on the side of its
+    /// destination) does when the available funds do not cover it.
+    pub dispute_policy: DisputePolicy,
+}
+
+impl Policy {
+    pub fn is_disputable(&self, kind: ChargeKind) -> bool {
+        match kind {
+            ChargeKind::Deposit => self.dispute_deposits,
+            ChargeKind::Withdrawal => self.dispute_withdrawals,
+            ChargeKind::Transfer { .. } => self.dispute_transfers,
+        }
+    }
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            allow_redispute: false,
+            dispute_deposits: true,
+            dispute_withdrawals: false,
+            dispute_transfers: true,
+            dispute_policy: DisputePolicy::default(),
+        }
+    }
+}
+
+/// What a dispute does when the available funds of its client do not cover the
+/// disputed amount (e.g., because the deposit was already withdrawn).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DisputePolicy {
+    /// The dispute is refused.
+    #[default]
+    Reject,
+    /// The whole amount is held all the same, and the available funds go below
+    /// zero (so that the loss shows).
+    AllowNegative,
+    /// Only the part of the amount which is available is held.
+    HoldPartial,
+}
+
+impl DisputePolicy {
+    /// How much of `amount` a dispute holds out of the `available` funds, or
+    /// `None` if the dispute is refused.
+    pub fn hold(self, available: Amount, amount: Amount) -> Option<Amount> {
+        match self {
+            Self::Reject => match available < amount {
+                true => None,
+                false => Some(amount),
+            },
+            Self::AllowNegative => Some(amount),
+            Self::HoldPartial => Some(amount.min(available.max(Amount::ZERO))),
+        }
+    }
+}
+
+impl FromStr for DisputePolicy {
+    type Err = &'static str;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        match src {
+            "reject" => Ok(Self::Reject),
+            "allow-negative" => Ok(Self::AllowNegative),
+            "hold-partial" => Ok(Self::HoldPartial),
+            _ => Err("Oops, the dispute policy must be either `reject`, \
+                 `allow-negative` or `hold-partial`."),
+        }
+    }
+}
@@ -0,0 +1,244 @@
+//! Saving and restoring the entire state of a `Core`.
+//!
+//! A snapshot is a headerless CSV file, whose first record is `version,1`.
+//! Each following record is either:
+//! - `client,<id>,<available>,<held>,<status>`, where `status` is one of
+//!   `active`, `frozen` or `closed`, or
+//! - `deposit|withdrawal,<client>,<tx>,<amount>,<state>[,<disputed>,<held>]`,
+//!   where `state` is one of `processed`, `disputed`, `resolved` or
+//!   `chargedback`, or
+//! - `transfer,<client>,<tx>,<amount>,<state>,<to>[,<disputed>,<held>]`.
+//!
+//! `disputed` and `held` are only recorded for a dispute which does not
+//! dispute (or does not hold) the whole amount of its charge.
+
+#[cfg(test)]
+mod tests;
+
+use std::io;
+use std::str::FromStr;
+
+use crate::client::Client;
+use crate::engine::core::store::Dispute;
+use crate::engine::core::store::TransactionWrapper;
+use crate::engine::core::Core;
+use crate::error::EngineError;
+use crate::transaction::Charge;
+use crate::transaction::ChargeKind;
+use crate::transaction::DisputeState;
+use crate::transaction::Transaction;
+use crate::transaction::Transfer;
+use crate::types::Amount;
+use crate::types::EngineResult;
+
+const VERSION: &str = "1";
+
+impl Core {
+    /// Writes every client and transaction of `self` into `dst`.
+    pub fn snapshot<W>(&self, dst: W) -> EngineResult<()>
+    where
+        W: io::Write,
+    {
+        let mut writer =
+            csv::WriterBuilder::new().flexible(true).from_writer(dst);
+        writer
+            .write_record(["version", VERSION])
+            .map_err(to_io_error)?;
+        for client in self.clients.values() {
+            writer
+                .write_record([
+                    "client",
+                    &client.id().to_string(),
+                    &client.available().to_string(),
+                    &client.held().to_string(),
+                    &client.status().to_string(),
+                ])
+                .map_err(to_io_error)?;
+        }
+        for entry in self.transactions.entries()? {
+            let (_, transaction_wrapper) = entry?;
+            let Some((kind, Charge { client, tx, amount })) =
+                transaction_wrapper.transaction().as_charge()
+            else {
+                continue;
+            };
+            let dispute = transaction_wrapper.dispute();
+            let partial = transaction_wrapper.state() == DisputeState::Disputed
+                && (dispute.disputed != *amount || dispute.held != *amount);
+            let (kind, to) = match kind {
+                ChargeKind::Deposit => ("deposit", None),
+                ChargeKind::Withdrawal => ("withdrawal", None),
+                ChargeKind::Transfer { to } => ("transfer", Some(to)),
+            };
+            let state = match transaction_wrapper.state() {
+                DisputeState::Processed => "processed",
+                DisputeState::Disputed => "disputed",
+                DisputeState::Resolved => "resolved",
+                DisputeState::ChargedBack => "chargedback",
+            };
+            let mut record = vec![
+                kind.to_owned(),
+                client.to_string(),
+                tx.to_string(),
+                amount.to_string(),
+                state.to_owned(),
+            ];
+            record.extend(to.map(|to| to.to_string()));
+            if partial {
+                record.push(dispute.disputed.to_string());
+                record.push(dispute.held.to_string());
+            };
+            writer.write_record(&record).map_err(to_io_error)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Loads every client and transaction of the snapshot read from `src`
+    /// into `self`, which is expected to be empty.
+    ///
+    /// A snapshot which is malformed, or which conflicts with what `self`
+    /// already holds, is refused with `InvalidSnapshot`; `self` may then be
+    /// left partially restored.
+    pub fn restore<R>(&mut self, src: R) -> EngineResult<()>
+    where
+        R: io::Read,
+    {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(src);
+        let mut record = csv::StringRecord::new();
+        let mut versioned = false;
+        loop {
+            let line = reader.position().line();
+            let invalid = |reason: &str| EngineError::InvalidSnapshot {
+                line,
+                reason: reason.into(),
+            };
+            match reader.read_record(&mut record) {
+                Ok(true) => (),
+                Ok(false) => break,
+                Err(error) => return Err(invalid(&error.to_string())),
+            };
+            let fields = record.iter().collect::<Vec<_>>();
+            match (versioned, fields.as_slice()) {
+                (false, ["version", VERSION]) => versioned = true,
+                (false, ["version", _]) => {
+                    return Err(invalid("unsupported version"))
+                },
+                (false, _) => return Err(invalid("missing version")),
+                (true, ["client", id, available, held, status]) => {
+                    let client = Client::with_balances(
+                        parse(id)
+                            .ok_or_else(|| invalid("invalid client id"))?,
+                        parse(available)
+                            .ok_or_else(|| invalid("invalid amount"))?,
+                        parse(held).ok_or_else(|| invalid("invalid amount"))?,
+                        parse(status)
+                            .ok_or_else(|| invalid("invalid status"))?,
+                    );
+                    client
+                        .available()
+                        .checked_add(client.held())
+                        .ok_or_else(|| invalid("the total would overflow"))?;
+                    if self.clients.contains_key(&client.id()) {
+                        return Err(invalid("duplicate client"));
+                    };
+                    self.clients.insert(client.id(), client);
+                },
+                (true, [kind, client, tx, amount, state, rest @ ..]) => {
+                    let charge = Charge {
+                        client: parse(client)
+                            .ok_or_else(|| invalid("invalid client id"))?,
+                        tx: parse(tx)
+                            .ok_or_else(|| invalid("invalid transaction id"))?,
+                        amount: parse(amount)
+                            .ok_or_else(|| invalid("invalid amount"))?,
+                    };
+                    let state = match *state {
+                        "processed" => DisputeState::Processed,
+                        "disputed" => DisputeState::Disputed,
+                        "resolved" => DisputeState::Resolved,
+                        "chargedback" => DisputeState::ChargedBack,
+                        _ => return Err(invalid("invalid dispute state")),
+                    };
+                    let tx = charge.tx;
+                    let amount = charge.amount;
+                    let (transaction, dispute) = match (*kind, rest) {
+                        ("deposit", [] | [_, _]) => {
+                            (Transaction::Deposit(charge), rest)
+                        },
+                        ("withdrawal", [] | [_, _]) => {
+                            (Transaction::Withdrawal(charge), rest)
+                        },
+                        ("transfer", [to, dispute @ ..])
+                            if matches!(dispute.len(), 0 | 2) =>
+                        {
+                            let to = parse(to)
+                                .ok_or_else(|| invalid("invalid client id"))?;
+                            let transfer = Transfer { charge, to };
+                            (Transaction::Transfer(transfer), dispute)
+                        },
+                        _ => return Err(invalid("unknown record type")),
+                    };
+                    let known = |client| self.clients.contains_key(&client);
+                    let destination = match transaction.as_charge() {
+                        Some((ChargeKind::Transfer { to }, _)) => Some(to),
+                        _ => None,
+                    };
+                    if !known(transaction.client())
+                        || !destination.is_none_or(known)
+                    {
+                        return Err(invalid("unknown client"));
+                    };
+                    if self.transactions.get(tx)?.is_some() {
+                        return Err(invalid("duplicate transaction"));
+                    };
+                    let mut transaction_wrapper =
+                        TransactionWrapper::new(transaction, state);
+                    if let [disputed, held] = dispute {
+                        let disputed = parse::<Amount>(disputed)
+                            .filter(|disputed| {
+                                state == DisputeState::Disputed
+                                    && *disputed > Amount::ZERO
+                                    && *disputed <= amount
+                            })
+                            .ok_or_else(|| {
+                                invalid("invalid disputed amount")
+                            })?;
+                        let held = parse::<Amount>(held)
+                            .filter(|held| {
+                                *held >= Amount::ZERO && *held <= disputed
+                            })
+                            .ok_or_else(|| invalid("invalid held amount"))?;
+                        transaction_wrapper
+                            .set_dispute(Dispute { disputed, held });
+                    };
+                    self.transactions.insert(tx, transaction_wrapper)?;
+                },
+                (true, _) => return Err(invalid("unknown record type")),
+            };
+        }
+        match versioned {
+            true => Ok(()),
+            false => Err(EngineError::InvalidSnapshot {
+                line: 1,
+                reason: "missing version".into(),
+            }),
+        }
+    }
+}
+
+fn parse<T>(field: &str) -> Option<T>
+where
+    T: FromStr,
+{
+    field.parse().ok()
+}
+
+fn to_io_error(error: csv::Error) -> EngineError {
+    EngineError::Io {
+        reason: error.to_string(),
+    }
+}
@@ -0,0 +1,258 @@
+use crate::client::Status;
+use crate::engine::core::Core;
+use crate::engine::core::DisputePolicy;
+use crate::engine::core::Policy;
+use crate::error::EngineError;
+use crate::transaction::DisputeState;
+use crate::types::EngineResult;
+
+fn restore(snapshot: &str) -> EngineResult<Core> {
+    let mut core = Core::default();
+    core.restore(snapshot.as_bytes())?;
+    Ok(core)
+}
+
+fn invalid(line: u64, reason: &str) -> EngineError {
+    EngineError::InvalidSnapshot {
+        line,
+        reason: reason.into(),
+    }
+}
+
+#[test]
+fn snapshot() {
+    let mut core = Core::default();
+    process!([
+        transaction!(["deposit", 1, 1, 2.0]),
+        transaction!(["deposit", 1, 2, 1.5]),
+        transaction!(["deposit", 2, 3, 1.0]),
+        transaction!(["deposit", 2, 4, 3.0]),
+        transaction!(["withdrawal", 2, 5, 1.0]),
+        transaction!(["dispute", 1, 1]),
+        transaction!(["dispute", 2, 4]),
+        transaction!(["chargeback", 2, 4]),
+    ] -> core);
+    let mut dst = vec![];
+    core.snapshot(&mut dst).unwrap();
+
+    assert_eq!(
+        String::from_utf8(dst).unwrap(),
+        "version,1\n\
+         client,1,1.5000,2.0000,active\n\
+         client,2,0.0000,0.0000,frozen\n\
+         deposit,1,1,2.0000,disputed\n\
+         deposit,1,2,1.5000,processed\n\
+         deposit,2,3,1.0000,processed\n\
+         deposit,2,4,3.0000,chargedback\n\
+         withdrawal,2,5,1.0000,processed\n"
+    );
+}
+
+#[test]
+fn roundtrip() {
+    let mut core = Core::default();
+    process!([
+        transaction!(["deposit", 1, 1, 2.0]),
+        transaction!(["deposit", 2, 2, 1.0]),
+        transaction!(["dispute", 1, 1]),
+    ] -> core);
+    let mut dst = vec![];
+    core.snapshot(&mut dst).unwrap();
+    let mut restored = Core::new(Policy::default());
+    restored.restore(dst.as_slice()).unwrap();
+
+    // Disputes can refer to transactions from before the snapshot.
+    process!([
+        transaction!(["resolve", 1, 1]),
+        transaction!(["dispute", 2, 2]),
+    ] -> restored);
+    assert_eq!(
+        restored.process(transaction!(["deposit", 2, 1, 1.0])),
+        Err(EngineError::DuplicateTx { tx: 1 })
+    );
+    assert_eq!(
+        restored.transactions.get(1).unwrap().unwrap().state(),
+        DisputeState::Resolved
+    );
+    assert_clients_eq!(
+        restored
+            == [client!([1, 2.0, 0.0, false]), client!([2, 0.0, 1.0, false]),]
+    );
+}
+
+#[test]
+fn roundtrip_transfers() {
+    let mut core = Core::default();
+    process!([
+        transaction!(["deposit", 1, 1, 2.0]),
+        transaction!(["transfer", 1, 2, 0.5, 2]),
+        transaction!(["dispute", 1, 2]),
+    ] -> core);
+    let mut dst = vec![];
+    core.snapshot(&mut dst).unwrap();
+    let snapshot = String::from_utf8(dst).unwrap();
+    assert_eq!(
+        snapshot,
+        "version,1\n\
+         client,1,1.5000,0.0000,active\n\
+         client,2,0.0000,0.5000,active\n\
+         deposit,1,1,2.0000,processed\n\
+         transfer,1,2,0.5000,disputed,2\n"
+    );
+    let mut restored = restore(&snapshot).unwrap();
+
+    process!([transaction!(["chargeback", 1, 2])] -> restored);
+    assert_clients_eq!(
+        restored
+            == [client!([1, 2.0, 0.0, false]), client!([2, 0.0, 0.0, true]),]
+    );
+}
+
+#[test]
+fn roundtrip_partial_holds() {
+    let mut core = Core::new(Policy {
+        dispute_policy: DisputePolicy::HoldPartial,
+        ..Policy::default()
+    });
+    process!([
+        transaction!(["deposit", 1, 1, 2.0]),
+        transaction!(["withdrawal", 1, 2, 1.5]),
+        transaction!(["dispute", 1, 1]),
+    ] -> core);
+    let mut dst = vec![];
+    core.snapshot(&mut dst).unwrap();
+    let snapshot = String::from_utf8(dst).unwrap();
+    assert_eq!(
+        snapshot,
+        "version,1\n\
+         client,1,0.0000,0.5000,active\n\
+         deposit,1,1,2.0000,disputed,2.0000,0.5000\n\
+         withdrawal,1,2,1.5000,processed\n"
+    );
+    let mut restored = Core::new(core.policy().clone());
+    restored.restore(snapshot.as_bytes()).unwrap();
+
+    // Only the held part is released by a resolve.
+    process!([transaction!(["resolve", 1, 1])] -> restored);
+    assert_clients_eq!(restored == [client!([1, 0.5, 0.0, false])]);
+}
+
+#[test]
+fn restore_statuses() {
+    let core = restore(
+        "version,1\n\
+         client,1,1.0,0,frozen\n\
+         client,2,0,0,closed\n",
+    )
+    .unwrap();
+
+    assert_clients_eq!(
+        core == [
+            client!([1, 1.0, 0.0, Status::Frozen]),
+            client!([2, 0.0, 0.0, Status::Closed]),
+        ]
+    );
+}
+
+#[test]
+fn restore_empty() {
+    let core = restore("version,1\n").unwrap();
+    assert_clients_eq!(core == []);
+}
+
+#[test]
+fn restore_invalid() {
+    assert_eq!(restore("").err(), Some(invalid(1, "missing version")));
+    assert_eq!(
+        restore("client,1,0,0,active\n").err(),
+        Some(invalid(1, "missing version"))
+    );
+    assert_eq!(
+        restore("version,2\n").err(),
+        Some(invalid(1, "unsupported version"))
+    );
+    assert_eq!(
+        restore("version,1\nclient,1,0,0,maybe\n").err(),
+        Some(invalid(2, "invalid status"))
+    );
+    assert_eq!(
+        restore("version,1\nclient,1,0,0,active\nclient,1,0,0,active\n").err(),
+        Some(invalid(3, "duplicate client"))
+    );
+    assert_eq!(
+        restore("version,1\ndeposit,1,1,1.0,processed\n").err(),
+        Some(invalid(2, "unknown client"))
+    );
+    assert_eq!(
+        restore("version,1\nclient,1,0,0,active\ndeposit,1,1,1.0,lost\n").err(),
+        Some(invalid(3, "invalid dispute state"))
+    );
+    assert_eq!(
+        restore(
+            "version,1\n\
+             client,1,0,0,active\n\
+             deposit,1,1,1.0,processed\n\
+             withdrawal,1,1,1.0,processed\n"
+        )
+        .err(),
+        Some(invalid(4, "duplicate transaction"))
+    );
+    assert_eq!(
+        restore("version,1\ntransfer,1,2\n").err(),
+        Some(invalid(2, "unknown record type"))
+    );
+    assert_eq!(
+        restore(
+            "version,1\nclient,1,0,0,active\ndeposit,1,1,1.0,processed,2\n"
+        )
+        .err(),
+        Some(invalid(3, "unknown record type"))
+    );
+    assert_eq!(
+        restore(
+            "version,1\nclient,1,0,0,active\ndeposit,1,1,1.0,processed,1,1,1\n"
+        )
+        .err(),
+        Some(invalid(3, "unknown record type"))
+    );
+    assert_eq!(
+        restore(
+            "version,1\nclient,1,0,0,active\ndeposit,1,1,1.0,processed,1,0.5\n"
+        )
+        .err(),
+        Some(invalid(3, "invalid disputed amount"))
+    );
+    assert_eq!(
+        restore(
+            "version,1\nclient,1,0,0,active\ndeposit,1,1,1.0,disputed,1.5,1\n"
+        )
+        .err(),
+        Some(invalid(3, "invalid disputed amount"))
+    );
+    assert_eq!(
+        restore(
+            "version,1\nclient,1,0,0,active\ndeposit,1,1,1.0,disputed,0,0\n"
+        )
+        .err(),
+        Some(invalid(3, "invalid disputed amount"))
+    );
+    assert_eq!(
+        restore(
+            "version,1\nclient,1,0,0,active\ndeposit,1,1,1.0,disputed,0.5,1\n"
+        )
+        .err(),
+        Some(invalid(3, "invalid held amount"))
+    );
+    assert_eq!(
+        restore("version,1\nclient,1,0,0,active\ntransfer,1,1,1.0,processed\n")
+            .err(),
+        Some(invalid(3, "unknown record type"))
+    );
+    assert_eq!(
+        restore(
+            "version,1\nclient,1,0,0,active\ntransfer,1,1,1.0,processed,2\n"
+        )
+        .err(),
+        Some(invalid(3, "unknown client"))
+    );
+}
@@ -0,0 +1,279 @@
+use std::fs;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+use crate::engine::core::store::Dispute;
+use crate::engine::core::store::Entries;
+use crate::engine::core::store::TransactionStore;
+use crate::engine::core::store::TransactionWrapper;
+use crate::error::EngineError;
+use crate::transaction::Charge;
+use crate::transaction::ChargeKind;
+use crate::transaction::DisputeState;
+use crate::transaction::Transaction;
+use crate::transaction::Transfer;
+use crate::types::Amount;
+use crate::types::EngineResult;
+use crate::types::TransactionId;
+
+/// The size of a single, encoded transaction.
+///
+/// The layout of a record is:
+/// - `kind` (1 byte): `0` for an empty slot, `1` for a deposit, `2` for a
+///   withdrawal, and `3` for a transfer.
+/// - `state` (1 byte): the `DisputeState`, in declaration order.
+/// - `client` (2 bytes), `tx` (4 bytes), `amount` (8 bytes, in
+///   ten-thousandths), `to` (2 bytes, `0` unless the record is a transfer),
+///   `held` and `disputed` (8 bytes each, in ten-thousandths), all
+///   little-endian.
+const RECORD_SIZE: usize = 34;
+
+/// The number of consecutive ids which `FileStore` keeps track of as a whole.
+const BLOCK_SIZE: u64 = 1024;
+
+static FORKS: AtomicUsize = AtomicUsize::new(0);
+
+/// Keeps every transaction in a file, so that memory usage stays bounded
+/// regardless of how many transactions are processed.
+///
+/// The transaction with id `tx` lives at offset `tx * RECORD_SIZE`, so no index
+/// needs to be kept in memory. The file is sparse: on most filesystems, the
+/// slots of ids which were never used take up no space on disk.
+///
+/// Only a bitmap of the blocks of `BLOCK_SIZE` ids in which a transaction was
+/// inserted is kept in memory (taking up at most 512 KiB), so that iterating
+/// through the entries skips the unused blocks instead of reading them.
+pub struct FileStore {
+    file: File,
+    path: PathBuf,
+    temporary: bool,
+    blocks: Vec<u64>,
+}
+
+impl FileStore {
+    /// Creates an empty store at `path`, truncating any existing file.
+    pub fn create<P>(path: P) -> EngineResult<Self>
+    where
+        P: Into<PathBuf>,
+    {
+        let path = path.into();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)?;
+        Ok(Self {
+            file,
+            path,
+            temporary: false,
+            blocks: vec![],
+        })
+    }
+
+    fn offset(tx: TransactionId) -> u64 {
+        u64::from(tx) * RECORD_SIZE as u64
+    }
+
+    fn mark(&mut self, tx: TransactionId) {
+        let block = u64::from(tx) / BLOCK_SIZE;
+        let word = (block / 64) as usize;
+        if self.blocks.len() <= word {
+            self.blocks.resize(word + 1, 0);
+        };
+        self.blocks[word] |= 1 << (block % 64);
+    }
+
+    /// The first block, starting from `block`, in which a transaction was
+    /// inserted.
+    fn used_block(&self, block: u64) -> Option<u64> {
+        let blocks = self.blocks.len() as u64 * 64;
+        (block..blocks).find(|block| {
+            self.blocks[(block / 64) as usize] & (1 << (block % 64)) != 0
+        })
+    }
+}
+
+impl Drop for FileStore {
+    fn drop(&mut self) {
+        if self.temporary {
+            fs::remove_file(&self.path).ok();
+        };
+    }
+}
+
+impl TransactionStore for FileStore {
+    fn get(
+        &self,
+        tx: TransactionId,
+    ) -> EngineResult<Option<TransactionWrapper>> {
+        let mut file = &self.file;
+        let mut record = [0; RECORD_SIZE];
+        file.seek(SeekFrom::Start(Self::offset(tx)))?;
+        match file.read_exact(&mut record) {
+            Ok(()) => decode(tx, &record),
+            Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => {
+                Ok(None)
+            },
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    fn insert(
+        &mut self,
+        tx: TransactionId,
+        transaction_wrapper: TransactionWrapper,
+    ) -> EngineResult<()> {
+        let record = encode(&transaction_wrapper)?;
+        self.file.seek(SeekFrom::Start(Self::offset(tx)))?;
+        self.file.write_all(&record)?;
+        self.mark(tx);
+        Ok(())
+    }
+
+    fn clear(&mut self) -> EngineResult<()> {
+        self.file.set_len(0)?;
+        self.blocks.clear();
+        Ok(())
+    }
+
+    fn entries(&self) -> EngineResult<Entries<'_>> {
+        // A separate handle is opened, so that the cursor of `self.file` is
+        // left untouched.
+        let mut reader = BufReader::new(File::open(&self.path)?);
+        let mut next = 0;
+        let mut end = 0;
+        let entries = std::iter::from_fn(move || loop {
+            // Only the blocks in which a transaction was inserted are read.
+            if next == end {
+                next = self.used_block(next / BLOCK_SIZE)? * BLOCK_SIZE;
+                end = next + BLOCK_SIZE;
+                let offset = next * RECORD_SIZE as u64;
+                if let Err(error) = reader.seek(SeekFrom::Start(offset)) {
+                    return Some(Err(error.into()));
+                };
+            };
+            let current = TransactionId::try_from(next).ok()?;
+            let mut record = [0; RECORD_SIZE];
+            match reader.read_exact(&mut record) {
+                Ok(()) => (),
+                Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => {
+                    return None
+                },
+                Err(error) => return Some(Err(error.into())),
+            };
+            next += 1;
+            match decode(current, &record) {
+                Ok(Some(transaction_wrapper)) => {
+                    return Some(Ok((current, transaction_wrapper)))
+                },
+                Ok(None) => continue,
+                Err(error) => return Some(Err(error)),
+            };
+        });
+        Ok(Box::new(entries))
+    }
+
+    fn fork(&self) -> EngineResult<Box<dyn TransactionStore>> {
+        let fork = FORKS.fetch_add(1, Ordering::Relaxed);
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}.{}", std::process::id(), fork));
+        let mut store = Self::create(path)?;
+        store.temporary = true;
+        Ok(Box::new(store))
+    }
+}
+
+fn corrupt(tx: TransactionId) -> EngineError {
+    EngineError::Io {
+        reason: format!("the stored record of transaction {} is corrupt", tx),
+    }
+}
+
+fn encode(
+    transaction_wrapper: &TransactionWrapper,
+) -> EngineResult<[u8; RECORD_SIZE]> {
+    let (kind, Charge { client, tx, amount }) = transaction_wrapper
+        .transaction()
+        .as_charge()
+        .ok_or_else(|| EngineError::Io {
+            reason: "only deposits, withdrawals and transfers can be stored"
+                .into(),
+        })?;
+    let (kind, to): (u8, _) = match kind {
+        ChargeKind::Deposit => (1, 0),
+        ChargeKind::Withdrawal => (2, 0),
+        ChargeKind::Transfer { to } => (3, to),
+    };
+    let state: u8 = match transaction_wrapper.state() {
+        DisputeState::Processed => 0,
+        DisputeState::Disputed => 1,
+        DisputeState::Resolved => 2,
+        DisputeState::ChargedBack => 3,
+    };
+    let mut record = [0; RECORD_SIZE];
+    record[0] = kind;
+    record[1] = state;
+    record[2..4].copy_from_slice(&client.to_le_bytes());
+    record[4..8].copy_from_slice(&tx.to_le_bytes());
+    record[8..16].copy_from_slice(&amount.units().to_le_bytes());
+    record[16..18].copy_from_slice(&to.to_le_bytes());
+    let Dispute { disputed, held } = transaction_wrapper.dispute();
+    record[18..26].copy_from_slice(&held.units().to_le_bytes());
+    record[26..34].copy_from_slice(&disputed.units().to_le_bytes());
+    Ok(record)
+}
+
+fn decode(
+    tx: TransactionId,
+    record: &[u8; RECORD_SIZE],
+) -> EngineResult<Option<TransactionWrapper>> {
+    if record[0] == 0 {
+        return Ok(None);
+    };
+    let state = match record[1] {
+        0 => DisputeState::Processed,
+        1 => DisputeState::Disputed,
+        2 => DisputeState::Resolved,
+        3 => DisputeState::ChargedBack,
+        _ => return Err(corrupt(tx)),
+    };
+    let client = u16::from_le_bytes([record[2], record[3]]);
+    let stored_tx =
+        u32::from_le_bytes([record[4], record[5], record[6], record[7]]);
+    let mut amount = [0; 8];
+    amount.copy_from_slice(&record[8..16]);
+    let amount = Amount::from_units(i64::from_le_bytes(amount));
+    let to = u16::from_le_bytes([record[16], record[17]]);
+    let mut held = [0; 8];
+    held.copy_from_slice(&record[18..26]);
+    let held = Amount::from_units(i64::from_le_bytes(held));
+    let mut disputed = [0; 8];
+    disputed.copy_from_slice(&record[26..34]);
+    let disputed = Amount::from_units(i64::from_le_bytes(disputed));
+    let charge = Charge { client, tx, amount };
+    let transaction = match record[0] {
+        1 => Transaction::Deposit(charge),
+        2 => Transaction::Withdrawal(charge),
+        3 => Transaction::Transfer(Transfer { charge, to }),
+        _ => return Err(corrupt(tx)),
+    };
+    match stored_tx == tx {
+        true => {
+            let mut transaction_wrapper =
+                TransactionWrapper::new(transaction, state);
+            transaction_wrapper.set_dispute(Dispute { disputed, held });
+            Ok(Some(transaction_wrapper))
+        },
+        false => Err(corrupt(tx)),
+    }
+}
@@ -0,0 +1,48 @@
+use std::collections::BTreeMap;
+
+use crate::engine::core::store::Entries;
+use crate::engine::core::store::TransactionStore;
+use crate::engine::core::store::TransactionWrapper;
+use crate::types::EngineResult;
+use crate::types::TransactionId;
+
+/// Keeps every transaction in memory.
+#[derive(Default)]
+pub struct MemoryStore {
+    transactions: BTreeMap<TransactionId, TransactionWrapper>,
+}
+
+impl TransactionStore for MemoryStore {
+    fn get(
+        &self,
+        tx: TransactionId,
+    ) -> EngineResult<Option<TransactionWrapper>> {
+        Ok(self.transactions.get(&tx).cloned())
+    }
+
+    fn insert(
+        &mut self,
+        tx: TransactionId,
+        transaction_wrapper: TransactionWrapper,
+    ) -> EngineResult<()> {
+        self.transactions.insert(tx, transaction_wrapper);
+        Ok(())
+    }
+
+    fn clear(&mut self) -> EngineResult<()> {
+        self.transactions.clear();
+        Ok(())
+    }
+
+    fn entries(&self) -> EngineResult<Entries<'_>> {
+        let entries =
+            self.transactions.iter().map(|(tx, transaction_wrapper)| {
+                Ok((*tx, transaction_wrapper.clone()))
+            });
+        Ok(Box::new(entries))
+    }
+
+    fn fork(&self) -> EngineResult<Box<dyn TransactionStore>> {
+        Ok(Box::<Self>::default())
+    }
+}
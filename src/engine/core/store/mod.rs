@@ -0,0 +1,106 @@
+//! Storage for the transactions which `Core` keeps around, so that later
+//! disputes, resolves and chargebacks can refer to them.
+
+mod file;
+mod memory;
+#[cfg(test)]
+mod tests;
+
+pub use self::file::FileStore;
+pub use self::memory::MemoryStore;
+use crate::transaction::Charge;
+use crate::transaction::DisputeState;
+use crate::transaction::Transaction;
+use crate::types::Amount;
+use crate::types::EngineResult;
+use crate::types::TransactionId;
+
+/// The open dispute of a charge: the part of its amount which is `disputed`,
+/// and the funds which it `held` (which may be less than the disputed amount,
+/// depending on the `DisputePolicy`).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Dispute {
+    pub disputed: Amount,
+    pub held: Amount,
+}
+
+/// A charge (i.e., a deposit, a withdrawal or a transfer) which was processed,
+/// along with the stage of the dispute lifecycle in which it is.
+///
+/// A `TransactionStore` which persists wrappers rebuilds them with
+/// [`TransactionWrapper::new`] and the setters below.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TransactionWrapper {
+    transaction: Transaction,
+    state: DisputeState,
+    dispute: Dispute,
+}
+
+impl TransactionWrapper {
+    /// Wraps `transaction`, whose whole amount is disputed (and held) if it is
+    /// `Disputed`.
+    pub fn new(transaction: Transaction, state: DisputeState) -> Self {
+        let dispute = match (state, transaction.as_charge()) {
+            (DisputeState::Disputed, Some((_, Charge { amount, .. }))) => {
+                Dispute {
+                    disputed: *amount,
+                    held: *amount,
+                }
+            },
+            _ => Dispute::default(),
+        };
+        Self {
+            transaction,
+            state,
+            dispute,
+        }
+    }
+
+    pub fn transaction(&self) -> &Transaction {
+        &self.transaction
+    }
+
+    pub fn state(&self) -> DisputeState {
+        self.state
+    }
+
+    pub fn set_state(&mut self, state: DisputeState) {
+        self.state = state;
+    }
+
+    pub fn dispute(&self) -> Dispute {
+        self.dispute
+    }
+
+    pub fn set_dispute(&mut self, dispute: Dispute) {
+        self.dispute = dispute;
+    }
+}
+
+pub type Entries<'a> = Box<
+    dyn Iterator<Item = EngineResult<(TransactionId, TransactionWrapper)>> + 'a,
+>;
+
+/// Where `Core` keeps the charges it has processed.
+pub trait TransactionStore: Send {
+    fn get(
+        &self,
+        tx: TransactionId,
+    ) -> EngineResult<Option<TransactionWrapper>>;
+
+    /// Inserts `transaction_wrapper` under `tx`, replacing any previous one.
+    fn insert(
+        &mut self,
+        tx: TransactionId,
+        transaction_wrapper: TransactionWrapper,
+    ) -> EngineResult<()>;
+
+    /// Removes every stored transaction.
+    fn clear(&mut self) -> EngineResult<()>;
+
+    /// Every stored transaction, in ascending order of their ids.
+    fn entries(&self) -> EngineResult<Entries<'_>>;
+
+    /// Creates a new, empty store of the same kind as `self`.
+    fn fork(&self) -> EngineResult<Box<dyn TransactionStore>>;
+}
@@ -0,0 +1,126 @@
+use std::env;
+use std::path::PathBuf;
+
+use super::Dispute;
+use super::FileStore;
+use super::MemoryStore;
+use super::TransactionStore;
+use super::TransactionWrapper;
+use crate::transaction::DisputeState;
+
+fn temp_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("{}.{}.store", name, std::process::id()))
+}
+
+fn processed(transaction: crate::Transaction) -> TransactionWrapper {
+    TransactionWrapper::new(transaction, DisputeState::Processed)
+}
+
+fn roundtrip(store: &mut dyn TransactionStore) {
+    let deposit = processed(transaction!(["deposit", 1, 7, 1.5]));
+    let withdrawal = processed(transaction!(["withdrawal", 2, 3, 0.0001]));
+    let transfer = processed(transaction!(["transfer", 3, 9, 2.5, u16::MAX]));
+    store.insert(7, deposit.clone()).unwrap();
+    store.insert(3, withdrawal.clone()).unwrap();
+    store.insert(9, transfer.clone()).unwrap();
+    assert_eq!(store.get(9).unwrap(), Some(transfer.clone()));
+    assert_eq!(store.get(7).unwrap(), Some(deposit.clone()));
+    assert_eq!(store.get(3).unwrap(), Some(withdrawal.clone()));
+    assert_eq!(store.get(5).unwrap(), None);
+    assert_eq!(store.get(u32::MAX).unwrap(), None);
+
+    let mut disputed = deposit;
+    disputed.set_state(DisputeState::Disputed);
+    disputed.set_dispute(Dispute {
+        disputed: amount!(1.25),
+        held: amount!(0.75),
+    });
+    store.insert(7, disputed.clone()).unwrap();
+    assert_eq!(store.get(7).unwrap(), Some(disputed.clone()));
+
+    let entries = store
+        .entries()
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(entries, vec![(3, withdrawal), (7, disputed), (9, transfer)]);
+
+    let fork = store.fork().unwrap();
+    assert_eq!(fork.entries().unwrap().count(), 0);
+
+    store.clear().unwrap();
+    assert_eq!(store.get(7).unwrap(), None);
+    assert_eq!(store.entries().unwrap().count(), 0);
+}
+
+#[test]
+fn memory_store_roundtrip() {
+    roundtrip(&mut MemoryStore::default());
+}
+
+#[test]
+fn file_store_roundtrip() {
+    let path = temp_path("file_store_roundtrip");
+    roundtrip(&mut FileStore::create(&path).unwrap());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn file_store_skips_unused_ids() {
+    let path = temp_path("file_store_skips_unused_ids");
+    let mut store = FileStore::create(&path).unwrap();
+    let first = processed(transaction!(["deposit", 1, 1, 1.0]));
+    let last = processed(transaction!(["deposit", 2, u32::MAX, 2.0]));
+    store.insert(1, first.clone()).unwrap();
+    store.insert(u32::MAX, last.clone()).unwrap();
+    let entries = store
+        .entries()
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(entries, vec![(1, first), (u32::MAX, last)]);
+    drop(store);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn file_store_fork_is_removed_on_drop() {
+    let path = temp_path("file_store_fork_is_removed_on_drop");
+    let store = FileStore::create(&path).unwrap();
+    let fork = store.fork().unwrap();
+    let forks = || {
+        std::fs::read_dir(env::temp_dir())
+            .unwrap()
+            .filter_map(Result::ok)
+            .filter(|entry| {
+                let name = entry.file_name();
+                let prefix = path.file_name().unwrap().to_string_lossy();
+                let name = name.to_string_lossy();
+                name.starts_with(&*prefix) && name != prefix
+            })
+            .count()
+    };
+    assert_eq!(forks(), 1);
+    drop(fork);
+    assert_eq!(forks(), 0);
+    drop(store);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn core_with_file_store() {
+    let path = temp_path("core_with_file_store");
+    let store = FileStore::create(&path).unwrap();
+    let mut core = crate::Core::with_store(Default::default(), Box::new(store));
+    process!([
+        transaction!(["deposit", 1, 1, 2.0]),
+        transaction!(["deposit", 2, 2, 1.0]),
+        transaction!(["dispute", 1, 1]),
+        transaction!(["chargeback", 1, 1]),
+        transaction!(["dispute", 2, 2]),
+    ] -> core);
+    assert_clients_eq!(
+        core == [client!([1, 0.0, 0.0, true]), client!([2, 0.0, 1.0, false])]
+    );
+    std::fs::remove_file(path).unwrap();
+}
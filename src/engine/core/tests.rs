@@ -1,3 +1,31 @@
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use proptest::collection;
+use proptest::option;
+use proptest::prelude::*;
+
+use super::store::Entries;
+use super::store::MemoryStore;
+use super::store::TransactionStore;
+use super::store::TransactionWrapper;
+use super::Core;
+use super::DisputePolicy;
+use super::Policy;
+use crate::client::Client;
+use crate::client::Status;
+use crate::error::EngineError;
+use crate::transaction::AccountAction;
+use crate::transaction::Charge;
+use crate::transaction::ChargeRef;
+use crate::transaction::DisputeState;
+use crate::transaction::Transaction;
+use crate::transaction::Transfer;
+use crate::types::Amount;
+use crate::types::EngineResult;
+use crate::types::TransactionId;
+
 #[test]
 fn basic_transaction() {
     let id = 1;
@@ -9,7 +37,7 @@ fn basic_transaction() {
         ..Default::default()
     };
     process!([transaction!(["deposit", 1, 1, 1.0])] -> core);
-    assert_clients_eq!(core == [client!([1, 0.0, 0.0, false])]);
+    assert_clients_eq!(core == [client!([1, 1.0, 0.0, false])]);
 }
 
 #[test]
@@ -25,12 +53,13 @@ fn dispute() {
     process!([
         transaction!(["deposit", 1, 1, 1.0]),
         transaction!(["dispute", 1, 1]),
-        transaction!(["dispute", 1, 1]),
-        transaction!(["dispute", 1, 1]),
-        transaction!(["dispute", 1, 1]),
-        transaction!(["dispute", 1, 1]),
-        transaction!(["dispute", 1, 1]),
     ] -> core);
+    for _ in 0..5 {
+        assert_eq!(
+            core.process(transaction!(["dispute", 1, 1])),
+            Err(EngineError::ExcessiveDispute { tx: 1 })
+        );
+    }
     assert_clients_eq!(core == [client!([1, 0.0, 1.0, false])]);
 }
 
@@ -51,15 +80,17 @@ fn resolve() {
         transaction!(["deposit", 1, 1, 1.0]),
         transaction!(["dispute", 1, 1]),
         transaction!(["resolve", 1, 1]),
-        transaction!(["resolve", 1, 1]),
-        transaction!(["resolve", 1, 1]),
-        transaction!(["resolve", 1, 1]),
-        transaction!(["resolve", 1, 1]),
-        transaction!(["resolve", 1, 1]),
-        transaction!(["resolve", 1, 1]),
-        transaction!(["resolve", 1, 1]),
-        transaction!(["resolve", 1, 1]),
     ] -> core);
+    for _ in 0..8 {
+        assert_eq!(
+            core.process(transaction!(["resolve", 1, 1])),
+            Err(EngineError::IllegalTransition {
+                tx: 1,
+                from: DisputeState::Resolved,
+                to: DisputeState::Resolved,
+            })
+        );
+    }
     assert_clients_eq!(core == [client!([1, 1.0, 0.0, false])]);
 }
 
@@ -76,14 +107,17 @@ fn resolve_non_existent_transaction() {
 #[test]
 fn resolve_undisputed_deposit() {
     let mut core = super::Core::default();
-    process!([
-        transaction!(["deposit", 1, 1, 1.0]),
-        transaction!(["resolve", 1, 1]),
-        transaction!(["resolve", 1, 1]),
-        transaction!(["resolve", 1, 1]),
-        transaction!(["resolve", 1, 1]),
-        transaction!(["resolve", 1, 1]),
-    ] -> core);
+    process!([transaction!(["deposit", 1, 1, 1.0])] -> core);
+    for _ in 0..5 {
+        assert_eq!(
+            core.process(transaction!(["resolve", 1, 1])),
+            Err(EngineError::IllegalTransition {
+                tx: 1,
+                from: DisputeState::Processed,
+                to: DisputeState::Resolved,
+            })
+        );
+    }
     assert_clients_eq!(core == [client!([1, 1.0, 0.0, false])]);
 }
 
@@ -112,10 +146,15 @@ fn chargeback() {
 #[test]
 fn chargeback_non_disputed_transaction() {
     let mut core = super::Core::default();
-    process!([
-        transaction!(["deposit", 1, 1, 1.0]),
-        transaction!(["chargeback", 1, 1]),
-    ] -> core);
+    process!([transaction!(["deposit", 1, 1, 1.0])] -> core);
+    assert_eq!(
+        core.process(transaction!(["chargeback", 1, 1])),
+        Err(EngineError::IllegalTransition {
+            tx: 1,
+            from: DisputeState::Processed,
+            to: DisputeState::ChargedBack,
+        })
+    );
     assert_clients_eq!(core == [client!([1, 1.0, 0.0, false])]);
 }
 
@@ -144,3 +183,850 @@ fn transaction_after_chargeback() {
     ] -> core);
     assert_clients_eq!(core == [client!([1, 2.0, 0.0, true])]);
 }
+
+#[test]
+fn exact_decimal_arithmetic() {
+    let mut core = super::Core::default();
+    process!([
+        transaction!(["deposit", 1, 1, 0.1]),
+        transaction!(["deposit", 1, 2, 0.2]),
+        transaction!(["withdrawal", 1, 3, 0.3]),
+        transaction!(["deposit", 1, 4, 0.0001]),
+    ] -> core);
+    assert_clients_eq!(core == [client!([1, 0.0001, 0.0, false])]);
+}
+
+#[test]
+fn deposit_overflow() {
+    let mut core = super::Core::default();
+    process!([transaction!(["deposit", 1, 1, 900000000000000])] -> core);
+    assert_eq!(
+        core.process(transaction!(["deposit", 1, 2, 900000000000000])),
+        Err(EngineError::BalanceOverflow { client: 1 })
+    );
+    assert_clients_eq!(core == [client!([1, 900000000000000, 0.0, false])]);
+}
+
+#[test]
+fn typed_errors() {
+    let mut core = super::Core::default();
+    process!([
+        transaction!(["deposit", 1, 1, 1.0]),
+        transaction!(["withdrawal", 1, 2, 1.0]),
+    ] -> core);
+    assert_eq!(
+        core.process(transaction!(["deposit", 1, 1, 1.0])),
+        Err(EngineError::DuplicateTx { tx: 1 })
+    );
+    assert_eq!(
+        core.process(transaction!(["withdrawal", 1, 3, 1.0])),
+        Err(EngineError::InsufficientFunds { client: 1 })
+    );
+    assert_eq!(
+        core.process(transaction!(["dispute", 1, 4])),
+        Err(EngineError::UnknownTx { tx: 4 })
+    );
+    assert_eq!(
+        core.process(transaction!(["dispute", 1, 2])),
+        Err(EngineError::NotDisputable { tx: 2 })
+    );
+}
+
+#[test]
+fn locked_account_error() {
+    let mut core = super::Core::default();
+    process!([
+        transaction!(["deposit", 1, 1, 1.0]),
+        transaction!(["dispute", 1, 1]),
+        transaction!(["chargeback", 1, 1]),
+    ] -> core);
+    assert_eq!(
+        core.process(transaction!(["deposit", 1, 2, 1.0])),
+        Err(EngineError::AccountLocked { client: 1 })
+    );
+}
+
+#[test]
+fn cross_client_dispute() {
+    let mut core = super::Core::default();
+    process!([
+        transaction!(["deposit", 1, 1, 1.0]),
+        transaction!(["deposit", 2, 2, 1.0]),
+    ] -> core);
+    assert_eq!(
+        core.process(transaction!(["dispute", 2, 1])),
+        Err(EngineError::ClientMismatch {
+            client: 2,
+            tx: 1,
+            owner: 1
+        })
+    );
+    assert_clients_eq!(
+        core == [client!([1, 1.0, 0.0, false]), client!([2, 1.0, 0.0, false]),]
+    );
+}
+
+#[test]
+fn cross_client_resolve_and_chargeback() {
+    let mut core = super::Core::default();
+    process!([
+        transaction!(["deposit", 1, 1, 1.0]),
+        transaction!(["deposit", 2, 2, 1.0]),
+        transaction!(["dispute", 1, 1]),
+    ] -> core);
+    assert_eq!(
+        core.process(transaction!(["resolve", 2, 1])),
+        Err(EngineError::ClientMismatch {
+            client: 2,
+            tx: 1,
+            owner: 1
+        })
+    );
+    assert_eq!(
+        core.process(transaction!(["chargeback", 2, 1])),
+        Err(EngineError::ClientMismatch {
+            client: 2,
+            tx: 1,
+            owner: 1
+        })
+    );
+    assert_clients_eq!(
+        core == [client!([1, 0.0, 1.0, false]), client!([2, 1.0, 0.0, false]),]
+    );
+}
+
+#[test]
+#[should_panic]
+fn dispute_by_non_existent_client() {
+    let mut core = super::Core::default();
+    process!([
+        transaction!(["deposit", 1, 1, 1.0]),
+        transaction!(["dispute", 3, 1]),
+    ] -> core);
+}
+
+#[test]
+fn cross_client_resolve_of_undisputed_deposit() {
+    let mut core = super::Core::default();
+    process!([transaction!(["deposit", 1, 1, 1.0])] -> core);
+    assert_eq!(
+        core.process(transaction!(["resolve", 2, 1])),
+        Err(EngineError::ClientMismatch {
+            client: 2,
+            tx: 1,
+            owner: 1
+        })
+    );
+}
+
+#[test]
+fn redispute_resolved_transaction() {
+    let mut core = super::Core::default();
+    process!([
+        transaction!(["deposit", 1, 1, 1.0]),
+        transaction!(["dispute", 1, 1]),
+        transaction!(["resolve", 1, 1]),
+    ] -> core);
+    assert_eq!(
+        core.process(transaction!(["dispute", 1, 1])),
+        Err(EngineError::IllegalTransition {
+            tx: 1,
+            from: DisputeState::Resolved,
+            to: DisputeState::Disputed,
+        })
+    );
+    assert_clients_eq!(core == [client!([1, 1.0, 0.0, false])]);
+}
+
+#[test]
+fn redispute_resolved_transaction_when_allowed() {
+    let mut core = super::Core::new(super::Policy {
+        allow_redispute: true,
+        ..Default::default()
+    });
+    process!([
+        transaction!(["deposit", 1, 1, 1.0]),
+        transaction!(["dispute", 1, 1]),
+        transaction!(["resolve", 1, 1]),
+        transaction!(["dispute", 1, 1]),
+    ] -> core);
+    assert_clients_eq!(core == [client!([1, 0.0, 1.0, false])]);
+}
+
+#[test]
+fn double_chargeback() {
+    let mut core = super::Core::new(super::Policy {
+        allow_redispute: true,
+        ..Default::default()
+    });
+    process!([
+        transaction!(["deposit", 1, 1, 2.0]),
+        transaction!(["deposit", 1, 2, 1.0]),
+        transaction!(["dispute", 1, 2]),
+        transaction!(["chargeback", 1, 2]),
+    ] -> core);
+    let transaction_wrapper = core.transactions.get(2).unwrap().unwrap();
+    assert_eq!(transaction_wrapper.state(), DisputeState::ChargedBack);
+    assert_eq!(
+        core.process(transaction!(["dispute", 1, 2])),
+        Err(EngineError::IllegalTransition {
+            tx: 2,
+            from: DisputeState::ChargedBack,
+            to: DisputeState::Disputed,
+        })
+    );
+    assert_clients_eq!(core == [client!([1, 2.0, 0.0, true])]);
+}
+
+fn withdrawal_disputes() -> super::Core {
+    super::Core::new(super::Policy {
+        dispute_withdrawals: true,
+        ..Default::default()
+    })
+}
+
+#[test]
+fn dispute_withdrawal() {
+    let mut core = withdrawal_disputes();
+    process!([
+        transaction!(["deposit", 1, 1, 3.0]),
+        transaction!(["withdrawal", 1, 2, 1.0]),
+        transaction!(["dispute", 1, 2]),
+    ] -> core);
+    assert_clients_eq!(core == [client!([1, 2.0, 1.0, false])]);
+}
+
+#[test]
+fn resolve_withdrawal() {
+    let mut core = withdrawal_disputes();
+    process!([
+        transaction!(["deposit", 1, 1, 3.0]),
+        transaction!(["withdrawal", 1, 2, 1.0]),
+        transaction!(["dispute", 1, 2]),
+        transaction!(["resolve", 1, 2]),
+    ] -> core);
+    assert_clients_eq!(core == [client!([1, 2.0, 0.0, false])]);
+}
+
+#[test]
+fn chargeback_withdrawal() {
+    let mut core = withdrawal_disputes();
+    process!([
+        transaction!(["deposit", 1, 1, 3.0]),
+        transaction!(["withdrawal", 1, 2, 1.0]),
+        transaction!(["dispute", 1, 2]),
+        transaction!(["chargeback", 1, 2]),
+    ] -> core);
+    assert_clients_eq!(core == [client!([1, 3.0, 0.0, true])]);
+}
+
+#[test]
+fn dispute_withdrawal_of_emptied_account() {
+    let mut core = withdrawal_disputes();
+    process!([
+        transaction!(["deposit", 1, 1, 1.0]),
+        transaction!(["withdrawal", 1, 2, 1.0]),
+        transaction!(["dispute", 1, 2]),
+    ] -> core);
+    assert_clients_eq!(core == [client!([1, 0.0, 1.0, false])]);
+}
+
+#[test]
+fn dispute_deposits_disabled() {
+    let mut core = super::Core::new(super::Policy {
+        dispute_deposits: false,
+        dispute_withdrawals: true,
+        ..Default::default()
+    });
+    process!([transaction!(["deposit", 1, 1, 1.0])] -> core);
+    assert_eq!(
+        core.process(transaction!(["dispute", 1, 1])),
+        Err(EngineError::NotDisputable { tx: 1 })
+    );
+}
+
+#[test]
+fn transfer() {
+    let mut core = super::Core::default();
+    process!([
+        transaction!(["deposit", 1, 1, 3.0]),
+        transaction!(["transfer", 1, 2, 1.0, 2]),
+        transaction!(["transfer", 2, 3, 0.5, 1]),
+    ] -> core);
+    assert_clients_eq!(
+        core == [client!([1, 2.5, 0.0, false]), client!([2, 0.5, 0.0, false])]
+    );
+}
+
+#[test]
+fn transfer_is_atomic() {
+    let mut core = super::Core::default();
+    process!([
+        transaction!(["deposit", 1, 1, 3.0]),
+        transaction!(["deposit", 2, 2, 1.0]),
+        transaction!(["dispute", 2, 2]),
+        transaction!(["chargeback", 2, 2]),
+    ] -> core);
+    let errors = [
+        (
+            transaction!(["transfer", 1, 3, 5.0, 2]),
+            EngineError::InsufficientFunds { client: 1 },
+        ),
+        (
+            transaction!(["transfer", 1, 3, 1.0, 2]),
+            EngineError::AccountLocked { client: 2 },
+        ),
+        (
+            transaction!(["transfer", 2, 3, 1.0, 1]),
+            EngineError::AccountLocked { client: 2 },
+        ),
+        (
+            transaction!(["transfer", 1, 3, 1.0, 1]),
+            EngineError::SelfTransfer { client: 1 },
+        ),
+        (
+            transaction!(["transfer", 3, 3, 1.0, 1]),
+            EngineError::UnknownClient { client: 3 },
+        ),
+        (
+            transaction!(["transfer", 1, 1, 1.0, 3]),
+            EngineError::DuplicateTx { tx: 1 },
+        ),
+    ];
+    for (transaction, error) in errors {
+        assert_eq!(core.process(transaction), Err(error));
+    }
+    assert_eq!(core.transaction(3).unwrap(), None);
+    assert_clients_eq!(
+        core == [client!([1, 3.0, 0.0, false]), client!([2, 0.0, 0.0, true])]
+    );
+}
+
+#[test]
+fn dispute_transfer() {
+    let mut core = super::Core::default();
+    process!([
+        transaction!(["deposit", 1, 1, 3.0]),
+        transaction!(["transfer", 1, 2, 1.0, 2]),
+        transaction!(["dispute", 1, 2]),
+    ] -> core);
+    assert_eq!(
+        core.process(transaction!(["resolve", 2, 2])),
+        Err(EngineError::ClientMismatch {
+            client: 2,
+            tx: 2,
+            owner: 1,
+        })
+    );
+    assert_clients_eq!(
+        core == [client!([1, 2.0, 0.0, false]), client!([2, 0.0, 1.0, false])]
+    );
+}
+
+#[test]
+fn resolve_transfer() {
+    let mut core = super::Core::default();
+    process!([
+        transaction!(["deposit", 1, 1, 3.0]),
+        transaction!(["transfer", 1, 2, 1.0, 2]),
+        transaction!(["dispute", 1, 2]),
+        transaction!(["resolve", 1, 2]),
+    ] -> core);
+    assert_clients_eq!(
+        core == [client!([1, 2.0, 0.0, false]), client!([2, 1.0, 0.0, false])]
+    );
+}
+
+#[test]
+fn chargeback_transfer() {
+    let mut core = super::Core::default();
+    process!([
+        transaction!(["deposit", 1, 1, 3.0]),
+        transaction!(["transfer", 1, 2, 1.0, 2]),
+        transaction!(["dispute", 1, 2]),
+        transaction!(["chargeback", 1, 2]),
+    ] -> core);
+    assert_clients_eq!(
+        core == [client!([1, 3.0, 0.0, false]), client!([2, 0.0, 0.0, true])]
+    );
+}
+
+#[test]
+fn chargeback_transfer_into_locked_source() {
+    let mut core = super::Core::default();
+    process!([
+        transaction!(["deposit", 1, 1, 3.0]),
+        transaction!(["deposit", 1, 3, 1.0]),
+        transaction!(["transfer", 1, 2, 1.0, 2]),
+        transaction!(["dispute", 1, 2]),
+        transaction!(["dispute", 1, 3]),
+        transaction!(["chargeback", 1, 3]),
+    ] -> core);
+    assert_eq!(
+        core.process(transaction!(["chargeback", 1, 2])),
+        Err(EngineError::AccountLocked { client: 1 })
+    );
+    // Neither side of the transfer was touched.
+    assert_clients_eq!(
+        core == [client!([1, 2.0, 0.0, true]), client!([2, 0.0, 1.0, false])]
+    );
+}
+
+#[test]
+fn dispute_transfer_from_locked_source() {
+    let mut core = super::Core::default();
+    process!([
+        transaction!(["deposit", 1, 1, 3.0]),
+        transaction!(["transfer", 1, 2, 1.0, 2]),
+        transaction!(["freeze", 1, 3, "review"]),
+    ] -> core);
+    assert_eq!(
+        core.process(transaction!(["dispute", 1, 2])),
+        Err(EngineError::AccountLocked { client: 1 })
+    );
+    // The destination holds nothing, and the transfer is still undisputed.
+    let transaction = core.transaction(2).unwrap().unwrap();
+    assert_eq!(transaction.state(), DisputeState::Processed);
+    assert_clients_eq!(
+        core == [client!([1, 2.0, 0.0, true]), client!([2, 1.0, 0.0, false])]
+    );
+}
+
+#[test]
+fn freeze_and_unfreeze() {
+    let mut core = super::Core::default();
+    process!([
+        transaction!(["deposit", 1, 1, 2.0]),
+        transaction!(["freeze", 1, 2, "fraud_review"]),
+    ] -> core);
+    assert_eq!(
+        core.process(transaction!(["withdrawal", 1, 3, 1.0])),
+        Err(EngineError::AccountLocked { client: 1 })
+    );
+    assert_eq!(
+        core.process(transaction!(["freeze", 1, 4, "fraud_review"])),
+        Err(EngineError::AccountLocked { client: 1 })
+    );
+    process!([
+        transaction!(["unfreeze", 1, 5, "cleared"]),
+        transaction!(["withdrawal", 1, 6, 1.0]),
+    ] -> core);
+    assert_eq!(
+        core.process(transaction!(["unfreeze", 1, 7, "cleared"])),
+        Err(EngineError::AccountNotFrozen { client: 1 })
+    );
+    assert_eq!(
+        core.process(transaction!(["freeze", 2, 8, "fraud_review"])),
+        Err(EngineError::UnknownClient { client: 2 })
+    );
+    assert_clients_eq!(core == [client!([1, 1.0, 0.0, false])]);
+}
+
+#[test]
+fn unfreeze_after_chargeback() {
+    let mut core = super::Core::default();
+    process!([
+        transaction!(["deposit", 1, 1, 1.0]),
+        transaction!(["deposit", 1, 2, 2.0]),
+        transaction!(["dispute", 1, 1]),
+        transaction!(["chargeback", 1, 1]),
+        transaction!(["unfreeze", 1, 3, "false_positive"]),
+        transaction!(["deposit", 1, 4, 0.5]),
+    ] -> core);
+    assert_clients_eq!(core == [client!([1, 2.5, 0.0, false])]);
+}
+
+#[test]
+fn close() {
+    let mut core = super::Core::default();
+    process!([
+        transaction!(["deposit", 1, 1, 1.0]),
+        transaction!(["deposit", 2, 2, 1.0]),
+        transaction!(["dispute", 2, 2]),
+    ] -> core);
+    assert_eq!(
+        core.process(transaction!(["close", 1, 3, "customer_request"])),
+        Err(EngineError::AccountNotEmpty { client: 1 })
+    );
+    assert_eq!(
+        core.process(transaction!(["close", 2, 4, "customer_request"])),
+        Err(EngineError::AccountNotEmpty { client: 2 })
+    );
+    process!([
+        transaction!(["withdrawal", 1, 5, 1.0]),
+        transaction!(["freeze", 1, 6, "dormant"]),
+        transaction!(["close", 1, 7, "dormant"]),
+    ] -> core);
+    for transaction in [
+        transaction!(["deposit", 1, 8, 1.0]),
+        transaction!(["freeze", 1, 9, "dormant"]),
+        transaction!(["unfreeze", 1, 10, "dormant"]),
+        transaction!(["close", 1, 11, "dormant"]),
+    ] {
+        assert_eq!(
+            core.process(transaction),
+            Err(EngineError::AccountClosed { client: 1 })
+        );
+    }
+    assert_clients_eq!(
+        core == [
+            client!([1, 0.0, 0.0, Status::Closed]),
+            client!([2, 0.0, 1.0, false]),
+        ]
+    );
+}
+
+fn with_dispute_policy(dispute_policy: DisputePolicy) -> super::Core {
+    super::Core::new(Policy {
+        dispute_policy,
+        ..Policy::default()
+    })
+}
+
+#[test]
+fn dispute_withdrawn_deposit_rejected() {
+    let mut core = with_dispute_policy(DisputePolicy::Reject);
+    process!([
+        transaction!(["deposit", 1, 1, 2.0]),
+        transaction!(["withdrawal", 1, 2, 1.5]),
+    ] -> core);
+    assert_eq!(
+        core.process(transaction!(["dispute", 1, 1])),
+        Err(EngineError::InsufficientFunds { client: 1 })
+    );
+    assert_clients_eq!(core == [client!([1, 0.5, 0.0, false])]);
+}
+
+#[test]
+fn dispute_withdrawn_deposit_allowing_negative() {
+    let mut core = with_dispute_policy(DisputePolicy::AllowNegative);
+    process!([
+        transaction!(["deposit", 1, 1, 2.0]),
+        transaction!(["deposit", 1, 2, 3.0]),
+        transaction!(["withdrawal", 1, 3, 4.5]),
+        transaction!(["dispute", 1, 1]),
+        transaction!(["dispute", 1, 2]),
+    ] -> core);
+    assert_eq!(core.client(1).unwrap().available(), amount!(-4.5));
+    process!([
+        transaction!(["resolve", 1, 1]),
+        transaction!(["chargeback", 1, 2]),
+    ] -> core);
+    assert_clients_eq!(core == [client!([1, -2.5, 0.0, true])]);
+}
+
+#[test]
+fn dispute_withdrawn_deposit_holding_partial() {
+    let mut core = with_dispute_policy(DisputePolicy::HoldPartial);
+    process!([
+        transaction!(["deposit", 1, 1, 2.0]),
+        transaction!(["deposit", 1, 2, 3.0]),
+        transaction!(["withdrawal", 1, 3, 4.5]),
+        transaction!(["dispute", 1, 1]),
+        transaction!(["dispute", 1, 2]),
+    ] -> core);
+    let held = |tx| core.transaction(tx).unwrap().unwrap().dispute().held;
+    assert_eq!(held(1), amount!(0.5));
+    assert_eq!(held(2), amount!(0));
+    assert_eq!(core.client(1).unwrap().held(), amount!(0.5));
+
+    // A resolve releases what was held, whereas a chargeback reverses the
+    // whole deposit.
+    process!([
+        transaction!(["resolve", 1, 1]),
+        transaction!(["chargeback", 1, 2]),
+    ] -> core);
+    assert_clients_eq!(core == [client!([1, -2.5, 0.0, true])]);
+}
+
+#[test]
+fn dispute_policy_hold() {
+    let hold =
+        |policy: DisputePolicy, available| policy.hold(available, amount!(2));
+    assert_eq!(hold(DisputePolicy::Reject, amount!(3)), Some(amount!(2)));
+    assert_eq!(hold(DisputePolicy::Reject, amount!(1)), None);
+    assert_eq!(
+        hold(DisputePolicy::AllowNegative, amount!(-1)),
+        Some(amount!(2))
+    );
+    assert_eq!(
+        hold(DisputePolicy::HoldPartial, amount!(3)),
+        Some(amount!(2))
+    );
+    assert_eq!(
+        hold(DisputePolicy::HoldPartial, amount!(1)),
+        Some(amount!(1))
+    );
+    assert_eq!(
+        hold(DisputePolicy::HoldPartial, amount!(-1)),
+        Some(amount!(0))
+    );
+}
+
+#[test]
+fn partial_dispute_and_resolve() {
+    let mut core = super::Core::default();
+    process!([
+        transaction!(["deposit", 1, 1, 10.0]),
+        transaction!(["dispute", 1, 1, 4.0]),
+    ] -> core);
+    assert_eq!(core.client(1), Some(&client!([1, 6.0, 4.0, false])));
+
+    // A partial resolve leaves the rest of the charge disputed.
+    process!([transaction!(["resolve", 1, 1, 1.0])] -> core);
+    assert_eq!(core.client(1), Some(&client!([1, 7.0, 3.0, false])));
+    let transaction = core.transaction(1).unwrap().unwrap();
+    assert_eq!(transaction.state(), DisputeState::Disputed);
+    assert_eq!(transaction.dispute().disputed, amount!(3.0));
+
+    process!([transaction!(["resolve", 1, 1])] -> core);
+    let transaction = core.transaction(1).unwrap().unwrap();
+    assert_eq!(transaction.state(), DisputeState::Resolved);
+    assert_clients_eq!(core == [client!([1, 10.0, 0.0, false])]);
+}
+
+#[test]
+fn partial_chargeback() {
+    let mut core = withdrawal_disputes();
+    process!([
+        transaction!(["deposit", 1, 1, 10.0]),
+        transaction!(["deposit", 1, 2, 5.0]),
+        transaction!(["withdrawal", 1, 3, 5.0]),
+        transaction!(["dispute", 1, 1, 4.0]),
+        transaction!(["dispute", 1, 3, 2.0]),
+    ] -> core);
+    assert_eq!(core.client(1), Some(&client!([1, 6.0, 6.0, false])));
+
+    // Only the charged back part is reversed; the rest of the dispute is
+    // released.
+    process!([transaction!(["chargeback", 1, 1, 3.0])] -> core);
+    let transaction = core.transaction(1).unwrap().unwrap();
+    assert_eq!(transaction.state(), DisputeState::ChargedBack);
+    assert_clients_eq!(core == [client!([1, 7.0, 2.0, true])]);
+
+    let mut core = withdrawal_disputes();
+    process!([
+        transaction!(["deposit", 1, 1, 10.0]),
+        transaction!(["withdrawal", 1, 2, 5.0]),
+        transaction!(["dispute", 1, 2, 2.0]),
+        transaction!(["chargeback", 1, 2, 1.5]),
+    ] -> core);
+    assert_clients_eq!(core == [client!([1, 6.5, 0.0, true])]);
+}
+
+#[test]
+fn partial_transfer_chargeback() {
+    let mut core = super::Core::default();
+    process!([
+        transaction!(["deposit", 1, 1, 10.0]),
+        transaction!(["transfer", 1, 2, 4.0, 2]),
+        transaction!(["dispute", 1, 2, 3.0]),
+        transaction!(["chargeback", 1, 2, 1.0]),
+    ] -> core);
+    assert_clients_eq!(
+        core == [client!([1, 7.0, 0.0, false]), client!([2, 3.0, 0.0, true]),]
+    );
+}
+
+#[test]
+fn excessive_disputes() {
+    let mut core = super::Core::default();
+    process!([transaction!(["deposit", 1, 1, 10.0])] -> core);
+    assert_eq!(
+        core.process(transaction!(["dispute", 1, 1, 10.0001])),
+        Err(EngineError::ExcessiveDispute { tx: 1 })
+    );
+    process!([
+        transaction!(["dispute", 1, 1, 4.0]),
+        transaction!(["resolve", 1, 1, 3.0]),
+    ] -> core);
+    for transaction in [
+        transaction!(["resolve", 1, 1, 1.5]),
+        transaction!(["chargeback", 1, 1, 1.5]),
+    ] {
+        assert_eq!(
+            core.process(transaction),
+            Err(EngineError::ExcessiveSettlement { tx: 1 })
+        );
+    }
+    assert_clients_eq!(core == [client!([1, 9.0, 1.0, false])]);
+}
+
+#[test]
+fn cumulative_disputes() {
+    let mut core = super::Core::default();
+    process!([
+        transaction!(["deposit", 1, 1, 2.0]),
+        transaction!(["dispute", 1, 1, 1.0]),
+    ] -> core);
+
+    // Further disputes add up, and may not exceed the amount of the charge
+    // altogether.
+    assert_eq!(
+        core.process(transaction!(["dispute", 1, 1, 1.5])),
+        Err(EngineError::ExcessiveDispute { tx: 1 })
+    );
+    process!([transaction!(["dispute", 1, 1, 0.5])] -> core);
+    let transaction = core.transaction(1).unwrap().unwrap();
+    assert_eq!(transaction.dispute().disputed, amount!(1.5));
+    process!([transaction!(["dispute", 1, 1])] -> core);
+    assert_eq!(
+        core.process(transaction!(["dispute", 1, 1, 0.0001])),
+        Err(EngineError::ExcessiveDispute { tx: 1 })
+    );
+    assert_clients_eq!(core == [client!([1, 0.0, 2.0, false])]);
+}
+
+/// A `MemoryStore` whose inserts fail while `failing` is set.
+struct FlakyStore {
+    store: MemoryStore,
+    failing: Arc<AtomicBool>,
+}
+
+impl TransactionStore for FlakyStore {
+    fn get(
+        &self,
+        tx: TransactionId,
+    ) -> EngineResult<Option<TransactionWrapper>> {
+        self.store.get(tx)
+    }
+
+    fn insert(
+        &mut self,
+        tx: TransactionId,
+        transaction_wrapper: TransactionWrapper,
+    ) -> EngineResult<()> {
+        match self.failing.load(Ordering::SeqCst) {
+            true => Err(EngineError::Io {
+                reason: "flaky store".into(),
+            }),
+            false => self.store.insert(tx, transaction_wrapper),
+        }
+    }
+
+    fn clear(&mut self) -> EngineResult<()> {
+        self.store.clear()
+    }
+
+    fn entries(&self) -> EngineResult<Entries<'_>> {
+        self.store.entries()
+    }
+
+    fn fork(&self) -> EngineResult<Box<dyn TransactionStore>> {
+        self.store.fork()
+    }
+}
+
+/// Every client and stored transaction of `core`.
+fn state(
+    core: &Core,
+) -> (Vec<Client>, Vec<(TransactionId, TransactionWrapper)>) {
+    let clients = core.clients().into_iter().cloned().collect();
+    let transactions = core
+        .transactions
+        .entries()
+        .unwrap()
+        .collect::<EngineResult<_>>()
+        .unwrap();
+    (clients, transactions)
+}
+
+fn arb_policy() -> impl Strategy<Value = Policy> {
+    let dispute_policy = prop_oneof![
+        Just(DisputePolicy::Reject),
+        Just(DisputePolicy::AllowNegative),
+        Just(DisputePolicy::HoldPartial),
+    ];
+    (any::<bool>(), any::<bool>(), dispute_policy).prop_map(
+        |(allow_redispute, dispute_withdrawals, dispute_policy)| Policy {
+            allow_redispute,
+            dispute_withdrawals,
+            dispute_policy,
+            ..Policy::default()
+        },
+    )
+}
+
+/// Mostly small amounts, along with a few huge ones which overflow balances.
+fn arb_amount() -> impl Strategy<Value = Amount> {
+    prop_oneof![
+        9 => 1..=50_000_i64,
+        1 => i64::MAX - 50_000..=i64::MAX,
+    ]
+    .prop_map(Amount::from_units)
+}
+
+/// Transactions between a few clients and ids, so that most of them refer to
+/// one another (and many of them are refused).
+fn arb_transaction() -> impl Strategy<Value = Transaction> {
+    let client = || 1..=3_u16;
+    let tx = || 1..=6_u32;
+    let charge =
+        || {
+            (client(), tx(), arb_amount())
+                .prop_map(|(client, tx, amount)| Charge { client, tx, amount })
+        };
+    let charge_ref = || {
+        (client(), tx(), option::of(arb_amount()))
+            .prop_map(|(client, tx, amount)| ChargeRef { client, tx, amount })
+    };
+    let account_action = || {
+        (client(), tx()).prop_map(|(client, tx)| AccountAction {
+            client,
+            tx,
+            reason: "review".into(),
+        })
+    };
+    prop_oneof![
+        charge().prop_map(Transaction::Deposit),
+        charge().prop_map(Transaction::Withdrawal),
+        (charge(), client()).prop_map(|(charge, to)| {
+            Transaction::Transfer(Transfer { charge, to })
+        }),
+        charge_ref().prop_map(Transaction::Dispute),
+        charge_ref().prop_map(Transaction::Resolve),
+        charge_ref().prop_map(Transaction::Chargeback),
+        account_action().prop_map(Transaction::Freeze),
+        account_action().prop_map(Transaction::Unfreeze),
+        account_action().prop_map(Transaction::Close),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn refused_transaction_leaves_core_untouched(
+        policy in arb_policy(),
+        transactions in collection::vec(arb_transaction(), 1..60),
+    ) {
+        let mut core = Core::new(policy);
+        for transaction in transactions {
+            let before = state(&core);
+            if core.process(transaction).is_err() {
+                prop_assert_eq!(state(&core), before);
+            };
+        }
+    }
+
+    #[test]
+    fn failed_store_leaves_core_untouched(
+        policy in arb_policy(),
+        transactions in collection::vec(
+            (arb_transaction(), any::<bool>()),
+            1..60,
+        ),
+    ) {
+        let failing = Arc::new(AtomicBool::new(false));
+        let store = FlakyStore {
+            store: MemoryStore::default(),
+            failing: failing.clone(),
+        };
+        let mut core = Core::with_store(policy, Box::new(store));
+        for (transaction, fail) in transactions {
+            failing.store(fail, Ordering::SeqCst);
+            let before = state(&core);
+            if core.process(transaction).is_err() {
+                prop_assert_eq!(state(&core), before);
+            };
+        }
+    }
+}
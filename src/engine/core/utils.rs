@@ -1,60 +1,272 @@
 use std::collections::BTreeMap;
 
 use crate::client::Client;
-use crate::engine::core::TransactionWrapper;
+use crate::client::Status;
+use crate::engine::core::store::Dispute;
+use crate::engine::core::store::TransactionStore;
+use crate::engine::core::store::TransactionWrapper;
+use crate::engine::core::Policy;
+use crate::error::EngineError;
 use crate::transaction::Charge;
+use crate::transaction::ChargeKind;
+use crate::transaction::DisputeState;
 use crate::transaction::Transaction;
+use crate::types::Amount;
 use crate::types::ClientId;
 use crate::types::EngineResult;
 use crate::types::TransactionId;
 
-#[cfg(not(test))]
-pub(super) fn get_or_insert_client(
-    clients: &mut BTreeMap<ClientId, Client>,
+/// A copy of `client`, or a new client if it does not exist yet.
+pub(super) fn get_or_new_client(
+    clients: &BTreeMap<ClientId, Client>,
     client: ClientId,
-) -> &mut Client {
-    clients.entry(client).or_insert_with(|| Client::new(client))
-}
-
-#[cfg(test)]
-pub(super) fn get_or_insert_client(
-    clients: &mut BTreeMap<ClientId, Client>,
-    client: ClientId,
-) -> &mut Client {
-    clients.entry(client).or_insert_with(|| Client::new(client, 0.0, 0.0, false))
+) -> Client {
+    clients
+        .get(&client)
+        .cloned()
+        .unwrap_or_else(|| Client::new(client))
 }
 
 pub(super) fn assert_transaction_doesnt_exists(
-    transactions: &BTreeMap<TransactionId, TransactionWrapper>,
+    transactions: &dyn TransactionStore,
     tx: &TransactionId,
 ) -> EngineResult<()> {
-    let exists = transactions.contains_key(&tx);
+    let exists = transactions.get(*tx)?.is_some();
     match exists {
         false => Ok(()),
-        true => Err("Oops, a transaction with that id already exists."),
+        true => Err(EngineError::DuplicateTx { tx: *tx }),
     }
 }
 
-pub(super) fn get_transaction_wrapper<'a>(
-    transactions: &'a mut BTreeMap<TransactionId, TransactionWrapper>,
-    tx: &'a TransactionId,
-) -> EngineResult<&'a mut TransactionWrapper> {
-    transactions.get_mut(tx).ok_or_else(|| {
-        "Oops, a transaction with that id was not able to be found."
-    })
+pub(super) fn get_transaction_wrapper(
+    transactions: &dyn TransactionStore,
+    tx: &TransactionId,
+) -> EngineResult<TransactionWrapper> {
+    transactions
+        .get(*tx)?
+        .ok_or(EngineError::UnknownTx { tx: *tx })
 }
 
 pub(super) fn get_client<'a>(
-    clients: &'a mut BTreeMap<ClientId, Client>,
-    client: &'a ClientId,
-) -> EngineResult<&'a mut Client> {
-    clients.get_mut(client).ok_or_else(|| "Oops, a client with that id was not able to be found. You can only dispute transactions directed towards currently existing clients.")
-}
-
-pub(super) fn as_deposit<'a>(
-    transaction: &'a mut Transaction,
-) -> EngineResult<&'a Charge> {
-    transaction.as_deposit().ok_or_else(|| {
-        "Oops, only transactions of type 'deposit' can be disputable."
-    })
+    clients: &'a BTreeMap<ClientId, Client>,
+    client: &ClientId,
+) -> EngineResult<&'a Client> {
+    clients
+        .get(client)
+        .ok_or(EngineError::UnknownClient { client: *client })
+}
+
+pub(super) fn as_disputable<'a>(
+    transaction: &'a Transaction,
+    tx: &TransactionId,
+    policy: &Policy,
+) -> EngineResult<(ChargeKind, &'a Charge)> {
+    transaction
+        .as_charge()
+        .filter(|(kind, _)| policy.is_disputable(*kind))
+        .ok_or(EngineError::NotDisputable { tx: *tx })
+}
+
+pub(super) fn assert_client_matches(
+    owner: &ClientId,
+    client: &ClientId,
+    tx: &TransactionId,
+) -> EngineResult<()> {
+    match owner == client {
+        true => Ok(()),
+        false => Err(EngineError::ClientMismatch {
+            client: *client,
+            tx: *tx,
+            owner: *owner,
+        }),
+    }
+}
+
+pub(super) fn assert_transition(
+    from: DisputeState,
+    to: DisputeState,
+    tx: &TransactionId,
+    policy: &Policy,
+) -> EngineResult<()> {
+    match from.can_transition(to, policy.allow_redispute) {
+        true => Ok(()),
+        false => Err(EngineError::IllegalTransition { tx: *tx, from, to }),
+    }
+}
+
+/// Moves `amount` out of `source` and into `destination` (which is created if
+/// it does not exist yet); returns updated copies of both clients.
+pub(super) fn transfer(
+    clients: &BTreeMap<ClientId, Client>,
+    source: ClientId,
+    destination: ClientId,
+    amount: Amount,
+) -> EngineResult<Vec<Client>> {
+    if source == destination {
+        return Err(EngineError::SelfTransfer { client: source });
+    };
+    let mut source_client = get_client(clients, &source)?.clone();
+    let mut destination_client = match clients.get(&destination) {
+        Some(client) => client.clone(),
+        None => Client::with_balances(
+            destination,
+            Amount::ZERO,
+            Amount::ZERO,
+            Status::Active,
+        ),
+    };
+    source_client.withdraw(amount)?;
+    destination_client.deposit(amount)?;
+    Ok(vec![source_client, destination_client])
+}
+
+/// The amount which a dispute, resolve or chargeback moves into
+/// `next_state`: either the `amount` which it carries, or else the whole of
+/// the `charge` which is not disputed yet (for a dispute) or the whole of its
+/// open `dispute` (for a resolve or a chargeback), which that amount may not
+/// exceed.
+pub(super) fn settled_amount(
+    charge: &Charge,
+    dispute: Dispute,
+    amount: Option<Amount>,
+    next_state: DisputeState,
+    tx: &TransactionId,
+) -> EngineResult<Amount> {
+    let (limit, error) = match next_state {
+        DisputeState::Disputed => (
+            charge.amount.saturating_sub(dispute.disputed),
+            EngineError::ExcessiveDispute { tx: *tx },
+        ),
+        _ => (dispute.disputed, EngineError::ExcessiveSettlement {
+            tx: *tx,
+        }),
+    };
+    let amount = amount.unwrap_or(limit);
+    match amount > Amount::ZERO && amount <= limit {
+        true => Ok(amount),
+        false => Err(error),
+    }
+}
+
+/// Moves `amount` of a stored `charge` (whose open dispute is `dispute`) into
+/// `next_state`, applying the dispute, resolve or chargeback onto copies of its
+/// client(s); returns the dispute which the charge has afterwards, along with
+/// those updated clients.
+pub(super) fn settle(
+    clients: &BTreeMap<ClientId, Client>,
+    kind: ChargeKind,
+    charge: &Charge,
+    dispute: Dispute,
+    amount: Amount,
+    next_state: DisputeState,
+    policy: &Policy,
+) -> EngineResult<(Dispute, Vec<Client>)> {
+    let mut client = get_client(clients, &charge.client)?.clone();
+    match kind {
+        ChargeKind::Deposit => {
+            let dispute = settle_deposit(
+                &mut client,
+                dispute,
+                amount,
+                next_state,
+                policy,
+            )?;
+            Ok((dispute, vec![client]))
+        },
+        ChargeKind::Withdrawal => {
+            let dispute =
+                settle_withdrawal(&mut client, dispute, amount, next_state)?;
+            Ok((dispute, vec![client]))
+        },
+        // The destination of a transfer settles it as if it had been a
+        // deposit, and a chargeback returns `amount` into its source (which,
+        // like the client of a deposit, must not be locked).
+        ChargeKind::Transfer { to } => {
+            client.assert_not_locked()?;
+            let mut destination = get_client(clients, &to)?.clone();
+            let dispute = settle_deposit(
+                &mut destination,
+                dispute,
+                amount,
+                next_state,
+                policy,
+            )?;
+            if next_state == DisputeState::ChargedBack {
+                client.deposit(amount)?;
+            };
+            Ok((dispute, vec![client, destination]))
+        },
+    }
+}
+
+/// Moves `amount` of a deposit into `next_state`, onto its `client`.
+fn settle_deposit(
+    client: &mut Client,
+    dispute: Dispute,
+    amount: Amount,
+    next_state: DisputeState,
+    policy: &Policy,
+) -> EngineResult<Dispute> {
+    match next_state {
+        DisputeState::Disputed => client
+            .dispute(amount, policy.dispute_policy)
+            .map(|held| Dispute {
+                disputed: dispute.disputed.saturating_add(amount),
+                held: dispute.held.saturating_add(held),
+            }),
+        DisputeState::Resolved => {
+            let rest = resolve(dispute, amount);
+            client.resolve(released(dispute, rest)).map(|()| rest)
+        },
+        DisputeState::ChargedBack => client
+            .charge_back(amount, dispute.held)
+            .map(|()| Dispute::default()),
+        // No transition leads back to `Processed`.
+        DisputeState::Processed => Ok(dispute),
+    }
+}
+
+/// Moves `amount` of a withdrawal into `next_state`, onto its `client`.
+fn settle_withdrawal(
+    client: &mut Client,
+    dispute: Dispute,
+    amount: Amount,
+    next_state: DisputeState,
+) -> EngineResult<Dispute> {
+    match next_state {
+        DisputeState::Disputed => {
+            client.dispute_withdrawal(amount).map(|()| Dispute {
+                disputed: dispute.disputed.saturating_add(amount),
+                held: dispute.held.saturating_add(amount),
+            })
+        },
+        DisputeState::Resolved => {
+            let rest = resolve(dispute, amount);
+            client
+                .resolve_withdrawal(released(dispute, rest))
+                .map(|()| rest)
+        },
+        DisputeState::ChargedBack => client
+            .charge_back_withdrawal(amount, dispute.held)
+            .map(|()| Dispute::default()),
+        // No transition leads back to `Processed`.
+        DisputeState::Processed => Ok(dispute),
+    }
+}
+
+/// What is left of `dispute` once `amount` of it is resolved: the funds it
+/// holds are released down to what remains disputed.
+///
+/// `amount` never exceeds the disputed amount (see [`settled_amount`]).
+fn resolve(dispute: Dispute, amount: Amount) -> Dispute {
+    let disputed = dispute.disputed.saturating_sub(amount);
+    Dispute {
+        disputed,
+        held: dispute.held.min(disputed),
+    }
+}
+
+/// The held funds which going from `dispute` to `rest` releases.
+fn released(dispute: Dispute, rest: Dispute) -> Amount {
+    dispute.held.saturating_sub(rest.held)
 }
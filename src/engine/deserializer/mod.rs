@@ -1,29 +1,52 @@
 #[cfg(test)]
 mod tests;
 
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io;
+use std::io::BufRead;
+use std::path::Path;
 use std::path::PathBuf;
+use std::str::FromStr;
 
 use serde::Deserialize;
+use serde_json::value::RawValue;
 
+use crate::amount::PRECISION;
+use crate::error::EngineError;
+use crate::error::ParseErrorKind;
+use crate::error::Reject;
+use crate::transaction::AccountAction;
 use crate::transaction::Charge;
 use crate::transaction::ChargeRef;
 use crate::transaction::Transaction;
+use crate::transaction::Transfer;
 use crate::types::Amount;
 use crate::types::ClientId;
 use crate::types::EngineResult;
 use crate::types::TransactionId;
 
+const STDIN: &str = "-";
+
 #[derive(Deserialize)]
 #[cfg_attr(test, derive(Debug))]
-struct RawTransaction<'a> {
-    r#type: &'a str,
-    client: ClientId,
-    tx: TransactionId,
-    amount: Option<Amount>,
+pub(crate) struct RawTransaction<'a> {
+    pub(crate) r#type: &'a str,
+    pub(crate) client: ClientId,
+    pub(crate) tx: TransactionId,
+    pub(crate) amount: Option<&'a str>,
+    /// The destination of a transfer (from an optional `to` column).
+    #[serde(default)]
+    pub(crate) to: Option<ClientId>,
+    /// The reason code of an administrative action (from an optional `reason`
+    /// column).
+    #[serde(default)]
+    pub(crate) reason: Option<&'a str>,
 }
 
 impl<'a> TryFrom<RawTransaction<'a>> for Transaction {
-    type Error = &'static str;
+    type Error = ParseErrorKind;
 
     fn try_from(
         RawTransaction {
@@ -31,39 +54,320 @@ impl<'a> TryFrom<RawTransaction<'a>> for Transaction {
             client,
             tx,
             amount,
+            to,
+            reason,
         }: RawTransaction,
     ) -> Result<Self, Self::Error> {
-        fn get_amount(amount: Option<Amount>) -> EngineResult<Amount> {
-            amount.ok_or_else(|| {
-                "Unable to get the amount for this transaction type."
+        fn get_amount(amount: Option<&str>) -> Result<Amount, ParseErrorKind> {
+            let amount = amount.ok_or(ParseErrorKind::MissingAmount)?;
+            let digits = amount.trim_start_matches(['+', '-']);
+            let non_finite = ["nan", "inf", "infinity"]
+                .iter()
+                .any(|name| digits.eq_ignore_ascii_case(name));
+            if non_finite {
+                return Err(ParseErrorKind::NonFiniteAmount(amount.into()));
+            };
+            let parsed = amount.parse::<Amount>().map_err(|_| {
+                // A decimal which only has too many fractional digits is told
+                // apart from one which is not a decimal at all.
+                let excess =
+                    amount.split_once('.').is_some_and(|(whole, fraction)| {
+                        fraction.len() > PRECISION
+                            && fraction.bytes().all(|b| b.is_ascii_digit())
+                            && format!("{}.{}", whole, &fraction[..PRECISION])
+                                .parse::<Amount>()
+                                .is_ok()
+                    });
+                match excess {
+                    true => ParseErrorKind::ExcessPrecision(amount.into()),
+                    false => ParseErrorKind::InvalidAmount(amount.into()),
+                }
+            })?;
+            match parsed.cmp(&Amount::ZERO) {
+                Ordering::Greater => Ok(parsed),
+                Ordering::Equal => Err(ParseErrorKind::ZeroAmount),
+                Ordering::Less => {
+                    Err(ParseErrorKind::NegativeAmount(amount.into()))
+                },
+            }
+        }
+        fn assert_no_amount(
+            amount: Option<&str>,
+        ) -> Result<(), ParseErrorKind> {
+            match amount {
+                Some(amount) => {
+                    Err(ParseErrorKind::UnexpectedAmount(amount.into()))
+                },
+                None => Ok(()),
+            }
+        }
+        fn assert_no_destination(
+            to: Option<ClientId>,
+        ) -> Result<(), ParseErrorKind> {
+            match to {
+                Some(to) => Err(ParseErrorKind::UnexpectedDestination(to)),
+                None => Ok(()),
+            }
+        }
+        fn assert_no_reason(
+            reason: Option<&str>,
+        ) -> Result<(), ParseErrorKind> {
+            match reason {
+                Some(reason) => {
+                    Err(ParseErrorKind::UnexpectedReason(reason.into()))
+                },
+                None => Ok(()),
+            }
+        }
+        fn get_reason(reason: Option<&str>) -> Result<String, ParseErrorKind> {
+            let reason = reason
+                .filter(|reason| !reason.is_empty())
+                .ok_or(ParseErrorKind::MissingReason)?;
+            let valid = reason.bytes().all(|byte| {
+                byte.is_ascii_alphanumeric() || matches!(byte, b'_' | b'-')
+            });
+            match valid {
+                true => Ok(reason.to_owned()),
+                false => Err(ParseErrorKind::InvalidReason(reason.into())),
+            }
+        }
+        let charge = || -> Result<Charge, ParseErrorKind> {
+            assert_no_destination(to)?;
+            assert_no_reason(reason)?;
+            let amount = get_amount(amount)?;
+            Ok(Charge { client, tx, amount })
+        };
+        let charge_ref = || -> Result<ChargeRef, ParseErrorKind> {
+            assert_no_destination(to)?;
+            assert_no_reason(reason)?;
+            let amount = amount.map(|amount| get_amount(Some(amount)));
+            Ok(ChargeRef {
+                client,
+                tx,
+                amount: amount.transpose()?,
             })
+        };
+        let account_action = || -> Result<AccountAction, ParseErrorKind> {
+            assert_no_destination(to)?;
+            assert_no_amount(amount)?;
+            let reason = get_reason(reason)?;
+            Ok(AccountAction { client, tx, reason })
+        };
+        match r#type {
+            "deposit" => charge().map(Transaction::Deposit),
+            "withdrawal" => charge().map(Transaction::Withdrawal),
+            "transfer" => assert_no_reason(reason)
+                .and_then(|()| get_amount(amount))
+                .and_then(|amount| {
+                    let to = to.ok_or(ParseErrorKind::MissingDestination)?;
+                    let charge = Charge { client, tx, amount };
+                    Ok(Transaction::Transfer(Transfer { charge, to }))
+                }),
+            "dispute" => charge_ref().map(Transaction::Dispute),
+            "resolve" => charge_ref().map(Transaction::Resolve),
+            "chargeback" => charge_ref().map(Transaction::Chargeback),
+            "freeze" => account_action().map(Transaction::Freeze),
+            "unfreeze" => account_action().map(Transaction::Unfreeze),
+            "close" => account_action().map(Transaction::Close),
+            _ => Err(ParseErrorKind::UnknownType(r#type.into())),
+        }
+    }
+}
+
+/// A transaction as spelled in JSON, whose amount is kept as its original text
+/// (so that it is parsed exactly, rather than through a float).
+#[derive(Deserialize)]
+struct RawJsonTransaction<'a> {
+    #[serde(borrow)]
+    r#type: Cow<'a, str>,
+    client: ClientId,
+    tx: TransactionId,
+    #[serde(borrow, default)]
+    amount: Option<&'a RawValue>,
+    #[serde(default)]
+    to: Option<ClientId>,
+    #[serde(borrow, default)]
+    reason: Option<Cow<'a, str>>,
+}
+
+impl RawJsonTransaction<'_> {
+    /// The text of the amount, whether it is a JSON number or a string.
+    fn amount(&self) -> Result<Option<Cow<'_, str>>, ParseErrorKind> {
+        let Some(amount) = self.amount else {
+            return Ok(None);
+        };
+        match amount.get().starts_with('"') {
+            true => serde_json::from_str::<Cow<str>>(amount.get())
+                .map(Some)
+                .map_err(|error| ParseErrorKind::Malformed(error.to_string())),
+            false => Ok(Some(Cow::Borrowed(amount.get()))),
+        }
+    }
+}
+
+/// The format of an input source.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum InputFormat {
+    /// A CSV file, with a `type, client, tx, amount` header.
+    #[default]
+    Csv,
+    /// One JSON object per line, with the same fields as the CSV format.
+    Jsonl,
+}
+
+impl InputFormat {
+    /// The format implied by the extension of `path`: JSON Lines for `.jsonl`
+    /// and `.ndjson` files, and CSV otherwise.
+    pub fn detect(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("jsonl" | "ndjson") => Self::Jsonl,
+            _ => Self::Csv,
         }
-        match &*r#type {
-            "deposit" => get_amount(amount).map(|amount| {
-                Transaction::Deposit(Charge { client, tx, amount })
-            }),
-            "withdrawal" => get_amount(amount).map(|amount| {
-                Transaction::Withdrawal(Charge { client, tx, amount })
-            }),
-            "dispute" => Ok(Transaction::Dispute(ChargeRef { client, tx })),
-            "resolve" => Ok(Transaction::Resolve(ChargeRef { client, tx })),
-            "chargeback" => {
-                Ok(Transaction::Chargeback(ChargeRef { client, tx }))
+    }
+}
+
+impl FromStr for InputFormat {
+    type Err = &'static str;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        match src {
+            "csv" => Ok(Self::Csv),
+            "jsonl" => Ok(Self::Jsonl),
+            _ => Err("Oops, the input format must be either `csv` or `jsonl`."),
+        }
+    }
+}
+
+/// Where a record was read from: its line number and its original text.
+///
+/// The text is only rendered on demand (i.e., when the record is refused).
+pub struct Origin<'a> {
+    pub line: u64,
+    raw_record: RawRecord<'a>,
+}
+
+enum RawRecord<'a> {
+    Csv(&'a csv::ByteRecord),
+    Line(&'a [u8]),
+}
+
+impl Origin<'_> {
+    pub fn record(&self) -> String {
+        let mut record = String::new();
+        self.write_record(&mut record);
+        record
+    }
+
+    /// Appends the original text of the record onto `dst`.
+    pub fn write_record(&self, dst: &mut String) {
+        match self.raw_record {
+            RawRecord::Csv(raw_record) => {
+                for (index, field) in raw_record.iter().enumerate() {
+                    if index > 0 {
+                        dst.push(',');
+                    };
+                    dst.push_str(&String::from_utf8_lossy(field));
+                }
+            },
+            RawRecord::Line(line) => {
+                dst.push_str(&String::from_utf8_lossy(line));
             },
-            _ => Err(""),
+        };
+    }
+
+    pub fn reject(&self, reason: EngineError) -> Reject {
+        Reject {
+            line: self.line,
+            record: self.record(),
+            reason,
         }
     }
 }
 
-pub fn deserialize<P, F>(src: P, mut callback: F) -> EngineResult<()>
+/// Streams each record of the file at `src` into `callback`, in the format
+/// implied by its extension (see [`InputFormat::detect`]).
+///
+/// If `src` is `-`, the records are read from `stdin` instead. See
+/// [`deserialize_reader`] for how failing records are handled.
+pub fn deserialize<P, F, R>(src: P, callback: F, reject: R) -> EngineResult<()>
 where
     P: Into<PathBuf>,
-    F: FnMut(Transaction),
+    F: FnMut(Transaction) -> EngineResult<()>,
+    R: FnMut(Reject) -> EngineResult<()>,
 {
     let src = src.into();
-    let mut reader = csv::Reader::from_path(src)
-        .map_err(|_| "Unable to read from the given source file.")?;
+    let format = InputFormat::detect(&src);
+    deserialize_reader(open(src)?, format, callback, reject)
+}
+
+/// Opens the file at `src` for reading (or `stdin`, if `src` is `-`).
+pub fn open<P>(src: P) -> EngineResult<Box<dyn io::Read>>
+where
+    P: Into<PathBuf>,
+{
+    let src = src.into();
+    match src.as_os_str() == STDIN {
+        true => Ok(Box::new(io::stdin().lock())),
+        false => Ok(Box::new(File::open(src)?)),
+    }
+}
+
+/// Streams each record read from `src` into `callback`.
+///
+/// Any record which cannot be parsed, or for which `callback` returns an
+/// error, is handed to `reject` (along with its line number and its original
+/// text) instead of being silently dropped.
+pub fn deserialize_reader<S, F, R>(
+    src: S,
+    format: InputFormat,
+    mut callback: F,
+    reject: R,
+) -> EngineResult<()>
+where
+    S: io::Read,
+    F: FnMut(Transaction) -> EngineResult<()>,
+    R: FnMut(Reject) -> EngineResult<()>,
+{
+    deserialize_reader_with_origins(
+        src,
+        format,
+        |transaction, _| callback(transaction),
+        reject,
+    )
+}
+
+/// Like [`deserialize_reader`], but also hands `callback` the [`Origin`] of
+/// each transaction, so that it can be reported later on.
+pub fn deserialize_reader_with_origins<S, F, R>(
+    src: S,
+    format: InputFormat,
+    callback: F,
+    reject: R,
+) -> EngineResult<()>
+where
+    S: io::Read,
+    F: FnMut(Transaction, &Origin) -> EngineResult<()>,
+    R: FnMut(Reject) -> EngineResult<()>,
+{
+    match format {
+        InputFormat::Csv => deserialize_csv(src, callback, reject),
+        InputFormat::Jsonl => deserialize_jsonl(src, callback, reject),
+    }
+}
+
+fn deserialize_csv<S, F, R>(
+    src: S,
+    mut callback: F,
+    mut reject: R,
+) -> EngineResult<()>
+where
+    S: io::Read,
+    F: FnMut(Transaction, &Origin) -> EngineResult<()>,
+    R: FnMut(Reject) -> EngineResult<()>,
+{
+    let mut reader = csv::Reader::from_reader(src);
     let mut raw_record = csv::ByteRecord::new();
+    let mut trimmed_record = csv::ByteRecord::new();
     let headers = reader
         .byte_headers()
         .map(|headers| {
@@ -71,15 +375,239 @@ where
             headers.trim();
             headers
         })
-        .map_err(|_| "Unable to read headers for this csv file.")?;
-    let headers = Some(&headers);
-    while reader.read_byte_record(&mut raw_record).unwrap_or_default() {
-        raw_record.trim();
-        raw_record
-            .deserialize::<RawTransaction>(headers)
-            .ok()
-            .and_then(|raw_transaction| raw_transaction.try_into().ok())
-            .map(&mut callback);
+        .map_err(|error| EngineError::ParseError {
+            line: 1,
+            column: None,
+            kind: ParseErrorKind::Malformed(error.to_string()),
+        })?;
+    loop {
+        let read = reader.read_byte_record(&mut raw_record);
+        let position = match &read {
+            Ok(_) => raw_record.position(),
+            Err(error) => error.position(),
+        };
+        let line = position.map_or(0, csv::Position::line);
+        let parse_error =
+            |column, kind| EngineError::ParseError { line, column, kind };
+        let origin = Origin {
+            line,
+            raw_record: RawRecord::Csv(&raw_record),
+        };
+        let result = match read {
+            Ok(false) => break,
+            Ok(true) => {
+                trim_into(&raw_record, &mut trimmed_record);
+                parse_csv(&trimmed_record, &headers, line)
+                    .and_then(|transaction| callback(transaction, &origin))
+            },
+            Err(error) if is_record_error(&error) => {
+                let column = error_column(&error);
+                let kind = ParseErrorKind::Malformed(error.to_string());
+                Err(parse_error(column, kind))
+            },
+            Err(error) => {
+                return Err(EngineError::Io {
+                    reason: error.to_string(),
+                })
+            },
+        };
+        if let Err(reason) = result {
+            reject(origin.reject(reason))?;
+        };
+    }
+    Ok(())
+}
+
+/// The (1-based) column of the field which caused `error`, if known.
+fn error_column(error: &csv::Error) -> Option<u64> {
+    match error.kind() {
+        csv::ErrorKind::Deserialize { err, .. } => {
+            err.field().map(|field| field + 1)
+        },
+        csv::ErrorKind::Utf8 { err, .. } => Some(err.field() as u64 + 1),
+        csv::ErrorKind::UnequalLengths {
+            expected_len, len, ..
+        } => Some(u64::min(*expected_len, *len) + 1),
+        _ => None,
+    }
+}
+
+/// The (1-based) column of the field which caused `kind`, if known.
+fn kind_column(
+    kind: &ParseErrorKind,
+    headers: &csv::ByteRecord,
+) -> Option<u64> {
+    let name: &[u8] = match kind {
+        ParseErrorKind::UnknownType(_) => b"type",
+        ParseErrorKind::MissingAmount
+        | ParseErrorKind::InvalidAmount(_)
+        | ParseErrorKind::ExcessPrecision(_)
+        | ParseErrorKind::NonFiniteAmount(_)
+        | ParseErrorKind::NegativeAmount(_)
+        | ParseErrorKind::ZeroAmount
+        | ParseErrorKind::UnexpectedAmount(_) => b"amount",
+        ParseErrorKind::MissingDestination
+        | ParseErrorKind::UnexpectedDestination(_) => b"to",
+        ParseErrorKind::MissingReason
+        | ParseErrorKind::InvalidReason(_)
+        | ParseErrorKind::UnexpectedReason(_) => b"reason",
+        ParseErrorKind::Malformed(_) => return None,
+    };
+    headers
+        .iter()
+        .position(|header| header == name)
+        .map(|index| index as u64 + 1)
+}
+
+/// Reads one JSON object per line (skipping blank lines), with the same fields
+/// as the CSV format.
+fn deserialize_jsonl<S, F, R>(
+    src: S,
+    mut callback: F,
+    mut reject: R,
+) -> EngineResult<()>
+where
+    S: io::Read,
+    F: FnMut(Transaction, &Origin) -> EngineResult<()>,
+    R: FnMut(Reject) -> EngineResult<()>,
+{
+    let mut reader = io::BufReader::new(src);
+    let mut raw_line = vec![];
+    let mut line = 0;
+    loop {
+        raw_line.clear();
+        if reader.read_until(b'\n', &mut raw_line)? == 0 {
+            break;
+        };
+        line += 1;
+        let text = raw_line.trim_ascii_end();
+        if text.trim_ascii().is_empty() {
+            continue;
+        };
+        let origin = Origin {
+            line,
+            raw_record: RawRecord::Line(text),
+        };
+        let result = parse_json(text, line)
+            .and_then(|transaction| callback(transaction, &origin));
+        if let Err(reason) = result {
+            reject(origin.reject(reason))?;
+        };
     }
     Ok(())
 }
+
+/// Parses a single `record` in `format`, outside of any input source (so a
+/// CSV record has no header, and is expected to hold the `type, client, tx,
+/// amount, to, reason` fields in this order, the last ones being optional).
+pub(crate) fn parse_record(
+    record: &[u8],
+    line: u64,
+    format: InputFormat,
+) -> EngineResult<Transaction> {
+    match format {
+        InputFormat::Csv => {
+            let mut reader = csv::ReaderBuilder::new()
+                .has_headers(false)
+                .flexible(true)
+                .from_reader(record);
+            let mut raw_record = csv::ByteRecord::new();
+            let mut trimmed_record = csv::ByteRecord::new();
+            reader.read_byte_record(&mut raw_record).map_err(|error| {
+                EngineError::ParseError {
+                    line,
+                    column: error_column(&error),
+                    kind: ParseErrorKind::Malformed(error.to_string()),
+                }
+            })?;
+            trim_into(&raw_record, &mut trimmed_record);
+            let headers = ["type", "client", "tx", "amount", "to", "reason"];
+            let headers = csv::ByteRecord::from(headers.to_vec());
+            parse_csv(&trimmed_record, &headers, line)
+        },
+        InputFormat::Jsonl => parse_json(record, line),
+    }
+}
+
+/// Parses a (trimmed) CSV record, whose fields are named by `headers`.
+fn parse_csv(
+    record: &csv::ByteRecord,
+    headers: &csv::ByteRecord,
+    line: u64,
+) -> EngineResult<Transaction> {
+    let parse_error =
+        |column, kind| EngineError::ParseError { line, column, kind };
+    record
+        .deserialize::<RawTransaction>(Some(headers))
+        .map_err(|error| {
+            let column = error_column(&error);
+            let kind = ParseErrorKind::Malformed(error.to_string());
+            parse_error(column, kind)
+        })
+        .and_then(|raw_transaction| {
+            Transaction::try_from(raw_transaction).map_err(|kind| {
+                let column = kind_column(&kind, headers);
+                parse_error(column, kind)
+            })
+        })
+}
+
+/// Parses a single JSON object.
+fn parse_json(text: &[u8], line: u64) -> EngineResult<Transaction> {
+    let parse_error =
+        |column, kind| EngineError::ParseError { line, column, kind };
+    serde_json::from_slice::<RawJsonTransaction>(text)
+        .map_err(|error| {
+            let column = Some(error.column() as u64);
+            let kind = ParseErrorKind::Malformed(json_reason(&error));
+            parse_error(column, kind)
+        })
+        .and_then(|raw_json_transaction| {
+            raw_json_transaction
+                .amount()
+                .and_then(|amount| {
+                    Transaction::try_from(RawTransaction {
+                        r#type: &raw_json_transaction.r#type,
+                        client: raw_json_transaction.client,
+                        tx: raw_json_transaction.tx,
+                        amount: amount.as_deref(),
+                        to: raw_json_transaction.to,
+                        reason: raw_json_transaction.reason.as_deref(),
+                    })
+                })
+                .map_err(|kind| parse_error(None, kind))
+        })
+}
+
+/// The message of `error`, without the position which `serde_json` appends to
+/// it (since it is relative to the line, rather than to the input).
+fn json_reason(error: &serde_json::Error) -> String {
+    let reason = error.to_string();
+    let position =
+        format!(" at line {} column {}", error.line(), error.column());
+    match reason.strip_suffix(&position) {
+        Some(reason) => reason.to_owned(),
+        None => reason,
+    }
+}
+
+/// Trims each field of `raw_record` into `trimmed_record`, reusing the latter's
+/// allocation (so that `raw_record` keeps the original text of the record).
+fn trim_into(
+    raw_record: &csv::ByteRecord,
+    trimmed_record: &mut csv::ByteRecord,
+) {
+    trimmed_record.clear();
+    raw_record
+        .iter()
+        .for_each(|field| trimmed_record.push_field(field.trim_ascii()));
+}
+
+fn is_record_error(error: &csv::Error) -> bool {
+    matches!(
+        error.kind(),
+        csv::ErrorKind::UnequalLengths { .. }
+            | csv::ErrorKind::Utf8 { .. }
+            | csv::ErrorKind::Deserialize { .. }
+    )
+}
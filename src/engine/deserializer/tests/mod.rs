@@ -1,8 +1,11 @@
+use crate::error::EngineError;
+use crate::error::ParseErrorKind;
+use crate::error::Reject;
 use crate::transaction::Charge;
 use crate::transaction::ChargeRef;
 use crate::transaction::Transaction;
 
-const BASE: &'static str = "src/engine/deserializer/tests";
+const BASE: &str = "src/engine/deserializer/tests";
 
 fn to_src(name: &'static str, valid: bool) -> String {
     let validity = match valid {
@@ -13,64 +16,630 @@ fn to_src(name: &'static str, valid: bool) -> String {
     format!("{}/{}.{}", BASE, validity, name)
 }
 
+fn collect(src: String) -> (Vec<Transaction>, Vec<Reject>) {
+    let mut transactions = vec![];
+    let mut rejects = vec![];
+    super::deserialize(
+        src,
+        |transaction| {
+            transactions.push(transaction);
+            Ok(())
+        },
+        |reject| {
+            rejects.push(reject);
+            Ok(())
+        },
+    )
+    .unwrap();
+    (transactions, rejects)
+}
+
 #[test]
 fn deserialize() {
     let src = to_src("deposit.csv", true);
-    super::deserialize(src, |transaction| {
-        assert_eq!(
-            transaction,
-            Transaction::Deposit(Charge {
+    assert_eq!(
+        collect(src),
+        (
+            vec![Transaction::Deposit(Charge {
                 client: 1,
                 tx: 1,
-                amount: 1.0
-            })
+                amount: amount!(1.0)
+            })],
+            vec![]
         )
-    })
-    .unwrap();
+    );
 
     let src = to_src("withdrawal.csv", true);
-    super::deserialize(src, |transaction| {
-        assert_eq!(
-            transaction,
-            Transaction::Withdrawal(Charge {
+    assert_eq!(
+        collect(src),
+        (
+            vec![Transaction::Withdrawal(Charge {
                 client: 1,
                 tx: 1,
-                amount: 1.0
-            })
+                amount: amount!(1.0)
+            })],
+            vec![]
         )
-    })
-    .unwrap();
+    );
 
     let src = to_src("dispute.csv", true);
-    super::deserialize(src, |transaction| {
-        assert_eq!(
-            transaction,
-            Transaction::Dispute(ChargeRef { client: 1, tx: 1 })
+    assert_eq!(
+        collect(src),
+        (
+            vec![Transaction::Dispute(ChargeRef {
+                client: 1,
+                tx: 1,
+                amount: None
+            })],
+            vec![]
         )
-    })
-    .unwrap();
+    );
 
     let src = to_src("resolve.csv", true);
-    super::deserialize(src, |transaction| {
-        assert_eq!(
-            transaction,
-            Transaction::Resolve(ChargeRef { client: 1, tx: 1 })
+    assert_eq!(
+        collect(src),
+        (
+            vec![Transaction::Resolve(ChargeRef {
+                client: 1,
+                tx: 1,
+                amount: None
+            })],
+            vec![]
         )
-    })
-    .unwrap();
+    );
 
     let src = to_src("chargeback.csv", true);
-    super::deserialize(src, |transaction| {
-        assert_eq!(
-            transaction,
-            Transaction::Chargeback(ChargeRef { client: 1, tx: 1 })
+    assert_eq!(
+        collect(src),
+        (
+            vec![Transaction::Chargeback(ChargeRef {
+                client: 1,
+                tx: 1,
+                amount: None
+            })],
+            vec![]
         )
-    })
-    .unwrap();
+    );
 }
 
 #[test]
 fn deserialize_invalid() {
     let src = to_src("deposit.csv", false);
-    super::deserialize(src, |_| unreachable!("No iterations should be performed... Panic if an iteration occurs.")).unwrap();
+    let (transactions, rejects) = collect(src);
+    assert!(transactions.is_empty());
+    assert_eq!(rejects.len(), 1);
+    assert_eq!(rejects[0].line, 2);
+    assert_eq!(rejects[0].record, "deposit, 1, 1, 1,");
+    assert!(matches!(rejects[0].reason, EngineError::ParseError {
+        line: 2,
+        column: Some(5),
+        ..
+    }));
+}
+
+#[test]
+fn rejects() {
+    let src = to_src("rejects.csv", false);
+    let (transactions, rejects) = collect(src);
+    assert_eq!(transactions.len(), 2);
+    let rejects = rejects
+        .into_iter()
+        .map(
+            |Reject {
+                 line,
+                 record,
+                 reason,
+             }| (line, record, reason),
+        )
+        .collect::<Vec<_>>();
+    assert!(matches!(&*rejects, [
+        (3, _, EngineError::ParseError {
+            line: 3,
+            column: Some(1),
+            kind: ParseErrorKind::UnknownType(_),
+        }),
+        (4, _, EngineError::ParseError {
+            line: 4,
+            column: Some(4),
+            kind: ParseErrorKind::MissingAmount,
+        }),
+        (5, _, EngineError::ParseError {
+            line: 5,
+            column: Some(2),
+            kind: ParseErrorKind::Malformed(_),
+        }),
+    ]));
+    assert_eq!(rejects[0].1, "refund, 1, 2, 1");
+}
+
+#[test]
+fn rejects_from_callback() {
+    let src = to_src("deposit.csv", true);
+    let mut rejects = vec![];
+    super::deserialize(
+        src,
+        |_| Err(EngineError::DuplicateTx { tx: 1 }),
+        |reject| {
+            rejects.push(reject);
+            Ok(())
+        },
+    )
+    .unwrap();
+    assert_eq!(rejects, vec![Reject {
+        line: 2,
+        record: "deposit, 1, 1, 1".into(),
+        reason: EngineError::DuplicateTx { tx: 1 },
+    }]);
+}
+
+#[test]
+fn strict() {
+    let src = to_src("rejects.csv", false);
+    let mut transactions = vec![];
+    let result = super::deserialize(
+        src,
+        |transaction| {
+            transactions.push(transaction);
+            Ok(())
+        },
+        |reject| Err(reject.into()),
+    );
+    assert_eq!(transactions.len(), 1);
+    let error = result.unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "line 3, column 1: unknown transaction type 'refund' (`refund, 1, 2, 1`)"
+    );
+}
+
+#[test]
+fn strict_from_callback() {
+    let src = to_src("deposit.csv", true);
+    let result = super::deserialize(
+        src,
+        |_| Err(EngineError::DuplicateTx { tx: 1 }),
+        |reject| Err(reject.into()),
+    );
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "line 2: transaction 1 already exists (`deposit, 1, 1, 1`)"
+    );
+}
+
+#[test]
+fn unknown_type() {
+    let raw_transaction = super::RawTransaction {
+        r#type: "refund",
+        client: 1,
+        tx: 1,
+        amount: None,
+        to: None,
+        reason: None,
+    };
+    assert_eq!(
+        Transaction::try_from(raw_transaction),
+        Err(ParseErrorKind::UnknownType("refund".into()))
+    );
+}
+
+fn try_from(
+    r#type: &str,
+    amount: Option<&str>,
+) -> Result<Transaction, ParseErrorKind> {
+    Transaction::try_from(super::RawTransaction {
+        r#type,
+        client: 1,
+        tx: 1,
+        amount,
+        to: None,
+        reason: None,
+    })
+}
+
+#[test]
+fn invalid_amounts() {
+    assert_eq!(
+        try_from("deposit", Some("-5")),
+        Err(ParseErrorKind::NegativeAmount("-5".into()))
+    );
+    assert_eq!(
+        try_from("withdrawal", Some("0.0000")),
+        Err(ParseErrorKind::ZeroAmount)
+    );
+    assert_eq!(
+        try_from("deposit", Some("NaN")),
+        Err(ParseErrorKind::NonFiniteAmount("NaN".into()))
+    );
+    assert_eq!(
+        try_from("deposit", Some("-inf")),
+        Err(ParseErrorKind::NonFiniteAmount("-inf".into()))
+    );
+    assert_eq!(
+        try_from("withdrawal", Some("1.00001")),
+        Err(ParseErrorKind::ExcessPrecision("1.00001".into()))
+    );
+    assert_eq!(
+        try_from("deposit", Some("-1.23456")),
+        Err(ParseErrorKind::ExcessPrecision("-1.23456".into()))
+    );
+    assert_eq!(
+        try_from("deposit", Some("1.2345x")),
+        Err(ParseErrorKind::InvalidAmount("1.2345x".into()))
+    );
+    assert_eq!(
+        try_from("deposit", Some("abc")),
+        Err(ParseErrorKind::InvalidAmount("abc".into()))
+    );
+    assert_eq!(
+        try_from("deposit", None),
+        Err(ParseErrorKind::MissingAmount)
+    );
+    assert_eq!(
+        try_from("deposit", Some("+1.5")),
+        Ok(Transaction::Deposit(Charge {
+            client: 1,
+            tx: 1,
+            amount: amount!(1.5),
+        }))
+    );
+}
+
+#[test]
+fn charge_ref_amounts() {
+    assert_eq!(
+        try_from("dispute", Some("0.5")),
+        Ok(Transaction::Dispute(ChargeRef {
+            client: 1,
+            tx: 1,
+            amount: Some(amount!(0.5)),
+        }))
+    );
+    assert_eq!(
+        try_from("chargeback", None),
+        Ok(Transaction::Chargeback(ChargeRef {
+            client: 1,
+            tx: 1,
+            amount: None,
+        }))
+    );
+    assert_eq!(
+        try_from("resolve", Some("0")),
+        Err(ParseErrorKind::ZeroAmount)
+    );
+    assert_eq!(
+        try_from("resolve", Some("-1")),
+        Err(ParseErrorKind::NegativeAmount("-1".into()))
+    );
+}
+
+#[test]
+fn unexpected_amounts() {
+    for r#type in ["freeze", "unfreeze", "close"] {
+        assert_eq!(
+            try_from(r#type, Some("1.0")),
+            Err(ParseErrorKind::UnexpectedAmount("1.0".into()))
+        );
+    }
+}
+
+#[test]
+fn transfers() {
+    let raw = |to| super::RawTransaction {
+        r#type: "transfer",
+        client: 1,
+        tx: 1,
+        amount: Some("1.5"),
+        to,
+        reason: None,
+    };
+    assert_eq!(
+        Transaction::try_from(raw(Some(2))),
+        Ok(transaction!(["transfer", 1, 1, 1.5, 2]))
+    );
+    assert_eq!(
+        Transaction::try_from(raw(None)),
+        Err(ParseErrorKind::MissingDestination)
+    );
+    assert_eq!(
+        try_from("transfer", None),
+        Err(ParseErrorKind::MissingAmount)
+    );
+    for r#type in ["deposit", "withdrawal", "dispute"] {
+        assert_eq!(
+            Transaction::try_from(super::RawTransaction {
+                r#type,
+                client: 1,
+                tx: 1,
+                amount: None,
+                to: Some(2),
+                reason: None,
+            }),
+            Err(ParseErrorKind::UnexpectedDestination(2))
+        );
+    }
+}
+
+#[test]
+fn account_actions() {
+    let raw = |r#type, amount, reason| super::RawTransaction {
+        r#type,
+        client: 1,
+        tx: 1,
+        amount,
+        to: None,
+        reason,
+    };
+    assert_eq!(
+        Transaction::try_from(raw("freeze", None, Some("fraud_review"))),
+        Ok(transaction!(["freeze", 1, 1, "fraud_review"]))
+    );
+    assert_eq!(
+        Transaction::try_from(raw("unfreeze", None, Some("false-positive"))),
+        Ok(transaction!(["unfreeze", 1, 1, "false-positive"]))
+    );
+    assert_eq!(
+        Transaction::try_from(raw("close", None, Some("Dormant2"))),
+        Ok(transaction!(["close", 1, 1, "Dormant2"]))
+    );
+    for reason in [None, Some("")] {
+        assert_eq!(
+            Transaction::try_from(raw("freeze", None, reason)),
+            Err(ParseErrorKind::MissingReason)
+        );
+    }
+    assert_eq!(
+        Transaction::try_from(raw("freeze", None, Some("fraud review"))),
+        Err(ParseErrorKind::InvalidReason("fraud review".into()))
+    );
+    assert_eq!(
+        Transaction::try_from(raw("close", Some("1.0"), Some("dormant"))),
+        Err(ParseErrorKind::UnexpectedAmount("1.0".into()))
+    );
+    for r#type in ["deposit", "transfer", "dispute"] {
+        assert_eq!(
+            Transaction::try_from(raw(r#type, Some("1.0"), Some("dormant"))),
+            Err(ParseErrorKind::UnexpectedReason("dormant".into()))
+        );
+    }
+}
+
+#[test]
+fn reason_column() {
+    let src = "type, client, tx, amount, reason\n\
+               deposit, 1, 1, 2,\n\
+               freeze, 1, 2,, fraud_review\n\
+               unfreeze, 1, 3,,\n";
+    let mut transactions = vec![];
+    let mut rejects = vec![];
+    super::deserialize_reader(
+        src.as_bytes(),
+        super::InputFormat::Csv,
+        |transaction| {
+            transactions.push(transaction);
+            Ok(())
+        },
+        |reject| {
+            rejects.push(reject.reason);
+            Ok(())
+        },
+    )
+    .unwrap();
+    assert_eq!(transactions, vec![
+        transaction!(["deposit", 1, 1, 2]),
+        transaction!(["freeze", 1, 2, "fraud_review"]),
+    ]);
+    assert_eq!(rejects, vec![EngineError::ParseError {
+        line: 4,
+        column: Some(5),
+        kind: ParseErrorKind::MissingReason,
+    }]);
+}
+
+#[test]
+fn destination_column() {
+    let src = "type, client, tx, amount, to\n\
+               transfer, 1, 1, 2, 2\n\
+               transfer, 1, 2, 2,\n\
+               deposit, 1, 3, 2, 2\n";
+    let mut transactions = vec![];
+    let mut rejects = vec![];
+    super::deserialize_reader(
+        src.as_bytes(),
+        super::InputFormat::Csv,
+        |transaction| {
+            transactions.push(transaction);
+            Ok(())
+        },
+        |reject| {
+            rejects.push(reject.reason);
+            Ok(())
+        },
+    )
+    .unwrap();
+    assert_eq!(transactions, vec![transaction!(["transfer", 1, 1, 2, 2])]);
+    assert_eq!(rejects, vec![
+        EngineError::ParseError {
+            line: 3,
+            column: Some(5),
+            kind: ParseErrorKind::MissingDestination,
+        },
+        EngineError::ParseError {
+            line: 4,
+            column: Some(5),
+            kind: ParseErrorKind::UnexpectedDestination(2),
+        },
+    ]);
+}
+
+#[test]
+fn invalid_amount_column() {
+    let src = "type, client, tx, amount\n\
+               deposit, 1, 1, -2\n";
+    let mut rejects = vec![];
+    super::deserialize_reader(
+        src.as_bytes(),
+        super::InputFormat::Csv,
+        |_| Ok(()),
+        |reject| {
+            rejects.push(reject.reason);
+            Ok(())
+        },
+    )
+    .unwrap();
+    assert_eq!(rejects, vec![EngineError::ParseError {
+        line: 2,
+        column: Some(4),
+        kind: ParseErrorKind::NegativeAmount("-2".into()),
+    }]);
+}
+
+#[test]
+fn deserialize_reader() {
+    let src = "type, client, tx, amount\n\
+               deposit, 1, 1, 1.5\n\
+               withdrawal, 2, 2, 0.25\n\
+               dispute, 1, 1,\n";
+    let mut transactions = vec![];
+    super::deserialize_reader(
+        src.as_bytes(),
+        super::InputFormat::Csv,
+        |transaction| {
+            transactions.push(transaction);
+            Ok(())
+        },
+        |reject| unreachable!("Unexpected reject: {:?}", reject),
+    )
+    .unwrap();
+    assert_eq!(transactions, vec![
+        Transaction::Deposit(Charge {
+            client: 1,
+            tx: 1,
+            amount: amount!(1.5)
+        }),
+        Transaction::Withdrawal(Charge {
+            client: 2,
+            tx: 2,
+            amount: amount!(0.25)
+        }),
+        Transaction::Dispute(ChargeRef {
+            client: 1,
+            tx: 1,
+            amount: None
+        }),
+    ]);
+}
+
+#[test]
+fn deserialize_missing_file() {
+    let src = to_src("missing.csv", true);
+    let result = super::deserialize(src, |_| Ok(()), |_| Ok(()));
+    assert!(matches!(result, Err(EngineError::Io { .. })));
+}
+
+#[test]
+fn deserialize_jsonl() {
+    let src = to_src("transactions.jsonl", true);
+    assert_eq!(
+        collect(src),
+        (
+            vec![
+                Transaction::Deposit(Charge {
+                    client: 1,
+                    tx: 1,
+                    amount: amount!(1.5)
+                }),
+                Transaction::Withdrawal(Charge {
+                    client: 2,
+                    tx: 2,
+                    amount: amount!(0.25)
+                }),
+                Transaction::Dispute(ChargeRef {
+                    client: 1,
+                    tx: 1,
+                    amount: None
+                }),
+                Transaction::Resolve(ChargeRef {
+                    client: 1,
+                    tx: 1,
+                    amount: None
+                }),
+            ],
+            vec![]
+        )
+    );
+}
+
+#[test]
+fn invalid_jsonl() {
+    let src = "{\"type\": \"deposit\", \"client\": 1, \"tx\": 1, \"amount\": -2}\n\
+               {\"type\": \"freeze\", \"client\": 1, \"tx\": 4, \"amount\": 1}\n\
+               {\"type\": \"refund\", \"client\": 1, \"tx\": 2}\n\
+               {\"type\": \"deposit\", \"client\": 1, \"tx\": 3, \"amount\": 1e400}\n\
+               {\"type\": \"deposit\", \"client\": 1}\n";
+    let mut rejects = vec![];
+    super::deserialize_reader(
+        src.as_bytes(),
+        super::InputFormat::Jsonl,
+        |_| Ok(()),
+        |reject| {
+            rejects.push(reject);
+            Ok(())
+        },
+    )
+    .unwrap();
+    let reasons = rejects
+        .iter()
+        .map(|reject| reject.reason.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(reasons[..4], [
+        EngineError::ParseError {
+            line: 1,
+            column: None,
+            kind: ParseErrorKind::NegativeAmount("-2".into()),
+        },
+        EngineError::ParseError {
+            line: 2,
+            column: None,
+            kind: ParseErrorKind::UnexpectedAmount("1".into()),
+        },
+        EngineError::ParseError {
+            line: 3,
+            column: None,
+            kind: ParseErrorKind::UnknownType("refund".into()),
+        },
+        EngineError::ParseError {
+            line: 4,
+            column: None,
+            kind: ParseErrorKind::InvalidAmount("1e400".into()),
+        },
+    ]);
+    assert!(matches!(&reasons[4], EngineError::ParseError {
+        line: 5,
+        column: Some(_),
+        kind: ParseErrorKind::Malformed(_),
+    }));
+    assert_eq!(
+        rejects[2].record,
+        "{\"type\": \"refund\", \"client\": 1, \"tx\": 2}"
+    );
+}
+
+#[test]
+fn input_format() {
+    use std::path::Path;
+
+    use super::InputFormat;
+
+    assert_eq!(
+        InputFormat::detect(Path::new("a.jsonl")),
+        InputFormat::Jsonl
+    );
+    assert_eq!(
+        InputFormat::detect(Path::new("a.ndjson")),
+        InputFormat::Jsonl
+    );
+    assert_eq!(InputFormat::detect(Path::new("a.csv")), InputFormat::Csv);
+    assert_eq!(InputFormat::detect(Path::new("-")), InputFormat::Csv);
+    assert_eq!("jsonl".parse(), Ok(InputFormat::Jsonl));
+    assert!("xml".parse::<InputFormat>().is_err());
 }
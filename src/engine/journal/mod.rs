@@ -0,0 +1,300 @@
+//! An append-only record of every transaction which `Core` accepted.
+//!
+//! Each entry holds the transaction, along with the changes it made to its
+//! client's balances (for a transfer, to those of its source) and, if it made
+//! or referred to a transfer, to those of the destination of that transfer.
+//! Replaying a journal onto an empty `Core` (with the same policy) rebuilds the
+//! exact same clients, and every replayed entry is checked against its
+//! recorded changes.
+
+#[cfg(test)]
+mod tests;
+
+use std::fs::OpenOptions;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::client::Client;
+use crate::engine::core::Core;
+use crate::engine::deserializer::RawTransaction;
+use crate::error::EngineError;
+use crate::transaction::ChargeKind;
+use crate::transaction::Transaction;
+use crate::types::Amount;
+use crate::types::ClientId;
+use crate::types::EngineResult;
+use crate::types::TransactionId;
+
+/// The changes which a single transaction made to its client.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Delta {
+    pub available: Amount,
+    pub held: Amount,
+    /// Whether the transaction locked the client.
+    pub locked: bool,
+}
+
+impl Delta {
+    /// The changes between `before` (if the client existed) and `after`.
+    pub(crate) fn between(before: Option<&Client>, after: &Client) -> Self {
+        let (available, held, locked) = match before {
+            Some(client) => {
+                (client.available(), client.held(), client.locked())
+            },
+            None => (Amount::ZERO, Amount::ZERO, false),
+        };
+        Self {
+            available: after.available().saturating_sub(available),
+            held: after.held().saturating_sub(held),
+            locked: after.locked() && !locked,
+        }
+    }
+}
+
+/// The changes which a single transaction made to the clients it involves.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Deltas {
+    pub client: Delta,
+    /// The changes to the destination of the transfer which the transaction
+    /// made or referred to, if any.
+    pub destination: Option<Delta>,
+}
+
+/// The columns of a journal, which cannot be appended onto if it has others.
+const HEADERS: [&str; 12] = [
+    "type",
+    "client",
+    "tx",
+    "amount",
+    "available",
+    "held",
+    "locked",
+    "to",
+    "reason",
+    "to_available",
+    "to_held",
+    "to_locked",
+];
+
+#[derive(Serialize, Deserialize)]
+struct RawEntry<'a> {
+    r#type: &'a str,
+    client: ClientId,
+    tx: TransactionId,
+    amount: Option<Amount>,
+    available: Amount,
+    held: Amount,
+    locked: bool,
+    /// The destination of a transfer.
+    to: Option<ClientId>,
+    /// The reason code of an administrative action.
+    reason: Option<&'a str>,
+    /// The changes to the destination of the transfer which the entry made or
+    /// referred to.
+    to_available: Option<Amount>,
+    to_held: Option<Amount>,
+    to_locked: Option<bool>,
+}
+
+impl<'a> RawEntry<'a> {
+    fn new(transaction: &'a Transaction, deltas: &Deltas) -> Self {
+        let Deltas {
+            client: delta,
+            destination,
+        } = deltas;
+        RawEntry {
+            r#type: transaction.type_name(),
+            client: transaction.client(),
+            tx: transaction.tx(),
+            amount: transaction.amount(),
+            available: delta.available,
+            held: delta.held,
+            locked: delta.locked,
+            to: match transaction.as_charge() {
+                Some((ChargeKind::Transfer { to }, _)) => Some(to),
+                _ => None,
+            },
+            reason: transaction.reason(),
+            to_available: destination.map(|delta| delta.available),
+            to_held: destination.map(|delta| delta.held),
+            to_locked: destination.map(|delta| delta.locked),
+        }
+    }
+}
+
+type Writer = csv::Writer<Box<dyn io::Write + Send>>;
+
+/// A handle onto a journal, which can be shared between several cores (e.g.,
+/// the shards of multi-threaded processing).
+///
+/// Every entry is flushed as soon as it is written, so that a run which stops
+/// midway has still journaled every transaction it accepted. Failing to write
+/// an entry does not undo its transaction (which was already applied), but is
+/// reported by the call which processed it.
+#[derive(Clone)]
+pub struct Journal {
+    writer: Arc<Mutex<Writer>>,
+}
+
+impl Journal {
+    /// Opens the journal at `path`, appending onto any existing entries.
+    ///
+    /// A journal whose columns differ from the current ones (e.g., one written
+    /// by an older version) is refused with `InvalidJournal`.
+    pub fn open<P>(path: P) -> EngineResult<Self>
+    where
+        P: Into<PathBuf>,
+    {
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path.into())?;
+        let empty = file.metadata()?.len() == 0;
+        if !empty {
+            let mut reader = csv::Reader::from_reader(&file);
+            let invalid = |reason: &str| EngineError::InvalidJournal {
+                line: 1,
+                reason: reason.into(),
+            };
+            let headers = reader
+                .headers()
+                .map_err(|error| invalid(&error.to_string()))?;
+            if !headers.iter().eq(HEADERS) {
+                return Err(invalid(
+                    "the journal has other columns, and cannot be appended onto",
+                ));
+            };
+        };
+        Ok(Self::new(file, empty))
+    }
+
+    /// Starts a journal which is written into `dst`, beginning with a header
+    /// row if `headers` is set.
+    pub fn new<W>(dst: W, headers: bool) -> Self
+    where
+        W: io::Write + Send + 'static,
+    {
+        let writer = csv::WriterBuilder::new()
+            .has_headers(headers)
+            .from_writer(Box::new(dst) as Box<dyn io::Write + Send>);
+        Self {
+            writer: Arc::new(Mutex::new(writer)),
+        }
+    }
+
+    /// Writes (and flushes) the entry of `transaction`, which made `deltas`.
+    pub(crate) fn record(
+        &self,
+        transaction: &Transaction,
+        deltas: &Deltas,
+    ) -> EngineResult<()> {
+        let mut writer =
+            self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        writer
+            .serialize(RawEntry::new(transaction, deltas))
+            .map_err(|error| EngineError::Io {
+                reason: error.to_string(),
+            })?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Flushes every entry written so far.
+    pub fn flush(&self) -> EngineResult<()> {
+        let mut writer =
+            self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Applies every entry of the journal read from `src` onto `core`.
+///
+/// An entry which cannot be applied, or whose changes differ from the
+/// recorded ones, is refused with `InvalidJournal` (which aborts the replay).
+pub fn replay<S>(core: &mut Core, src: S) -> EngineResult<()>
+where
+    S: io::Read,
+{
+    let mut reader = csv::Reader::from_reader(src);
+    let headers = reader
+        .headers()
+        .map_err(|error| EngineError::InvalidJournal {
+            line: 1,
+            reason: error.to_string(),
+        })?
+        .clone();
+    let mut record = csv::StringRecord::new();
+    loop {
+        let line = reader.position().line();
+        let invalid =
+            |reason: String| EngineError::InvalidJournal { line, reason };
+        match reader.read_record(&mut record) {
+            Ok(true) => (),
+            Ok(false) => break,
+            Err(error) => return Err(invalid(error.to_string())),
+        };
+        let RawEntry {
+            r#type,
+            client,
+            tx,
+            amount,
+            available,
+            held,
+            locked,
+            to,
+            reason,
+            to_available,
+            to_held,
+            to_locked,
+        } = record
+            .deserialize(Some(&headers))
+            .map_err(|error| invalid(error.to_string()))?;
+        let amount = amount.map(|amount| amount.to_string());
+        let raw_transaction = RawTransaction {
+            r#type,
+            client,
+            tx,
+            amount: amount.as_deref(),
+            to,
+            reason,
+        };
+        let transaction = Transaction::try_from(raw_transaction)
+            .map_err(|kind| invalid(kind.to_string()))?;
+        let destination = match (to_available, to_held, to_locked) {
+            (Some(available), Some(held), Some(locked)) => Some(Delta {
+                available,
+                held,
+                locked,
+            }),
+            (None, None, None) => None,
+            _ => {
+                return Err(invalid(
+                    "the changes to the destination are incomplete".into(),
+                ))
+            },
+        };
+        let deltas = core
+            .process_with_deltas(transaction)
+            .map_err(|error| invalid(error.to_string()))?;
+        let recorded = Deltas {
+            client: Delta {
+                available,
+                held,
+                locked,
+            },
+            destination,
+        };
+        if deltas != recorded {
+            return Err(invalid("the balances differ from the journal".into()));
+        };
+    }
+    Ok(())
+}
@@ -0,0 +1,256 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+
+use super::Journal;
+use crate::engine::core::Core;
+use crate::engine::Engine;
+use crate::error::EngineError;
+
+const SRC: &str = "type, client, tx, amount\n\
+                   deposit, 1, 1, 2\n\
+                   withdrawal, 1, 2, 5\n\
+                   deposit, 2, 3, 1.5\n\
+                   withdrawal, 2, 4, 0.5\n\
+                   dispute, 1, 1,\n\
+                   dispute, 2, 3,\n\
+                   chargeback, 2, 3,\n";
+
+const JOURNAL: &str = "type,client,tx,amount,available,held,locked,to,reason,\
+                       to_available,to_held,to_locked\n\
+                       deposit,1,1,2.0000,2.0000,0.0000,false,,,,,\n\
+                       deposit,2,3,1.5000,1.5000,0.0000,false,,,,,\n\
+                       withdrawal,2,4,0.5000,-0.5000,0.0000,false,,,,,\n\
+                       dispute,1,1,,-2.0000,2.0000,false,,,,,\n";
+
+fn temp_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("{}.{}.journal", name, std::process::id()))
+}
+
+fn journaled(name: &str, src: &str, threads: Option<usize>) -> PathBuf {
+    let path = temp_path(name);
+    let mut engine = Engine::default();
+    engine.set_journal(Journal::open(&path).unwrap());
+    match threads.and_then(NonZeroUsize::new) {
+        Some(threads) => engine
+            .process_reader_parallel(src.as_bytes(), threads, |_| Ok(()))
+            .unwrap(),
+        None => engine.process_reader(src.as_bytes(), |_| Ok(())).unwrap(),
+    };
+    path
+}
+
+fn write(engine: &Engine) -> String {
+    let mut dst = vec![];
+    engine.write(&mut dst).unwrap();
+    String::from_utf8(dst).unwrap()
+}
+
+#[test]
+fn records_accepted_transactions() {
+    let path = journaled("records_accepted_transactions", SRC, None);
+    let journal = fs::read_to_string(&path).unwrap();
+    fs::remove_file(path).unwrap();
+
+    // The overdrawing withdrawal and the dispute of a withdrawn deposit are
+    // refused, and so is the chargeback of the latter.
+    assert_eq!(journal, JOURNAL);
+}
+
+#[test]
+fn flushes_each_entry() {
+    let path = temp_path("flushes_each_entry");
+    let mut core = Core::default();
+    core.set_journal(Journal::open(&path).unwrap());
+    core.process(transaction!(["deposit", 1, 1, 2.0])).unwrap();
+    let journal = fs::read_to_string(&path).unwrap();
+    fs::remove_file(path).unwrap();
+
+    // The entry is on disk before the journal is flushed.
+    assert_eq!(
+        journal,
+        JOURNAL.lines().take(2).collect::<Vec<_>>().join("\n") + "\n"
+    );
+}
+
+#[test]
+fn reports_write_failures() {
+    struct Failing;
+
+    impl io::Write for Failing {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::other("disk full"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut core = Core::default();
+    core.set_journal(Journal::new(Failing, true));
+    assert!(matches!(
+        core.process(transaction!(["deposit", 1, 1, 2.0])),
+        Err(EngineError::Io { .. })
+    ));
+}
+
+#[test]
+fn appends_onto_existing_journal() {
+    let path = journaled("appends_onto_existing_journal", SRC, None);
+    let mut engine = Engine::default();
+    engine.replay(fs::File::open(&path).unwrap()).unwrap();
+    engine.set_journal(Journal::open(&path).unwrap());
+    let src = "type, client, tx, amount\n\
+               resolve, 1, 1,\n";
+    engine.process_reader(src.as_bytes(), |_| Ok(())).unwrap();
+    let journal = fs::read_to_string(&path).unwrap();
+    fs::remove_file(path).unwrap();
+
+    assert_eq!(
+        journal,
+        format!("{}resolve,1,1,,2.0000,-2.0000,false,,,,,\n", JOURNAL)
+    );
+}
+
+#[test]
+fn refuses_appending_onto_older_journal() {
+    let path = temp_path("refuses_appending_onto_older_journal");
+    let older = "type,client,tx,amount,available,held,locked\n\
+                 deposit,1,1,2.0000,2.0000,0.0000,false\n";
+    fs::write(&path, older).unwrap();
+    let result = Journal::open(&path).map(|_| ());
+    let journal = fs::read_to_string(&path).unwrap();
+    fs::remove_file(path).unwrap();
+
+    assert_eq!(
+        result,
+        Err(EngineError::InvalidJournal {
+            line: 1,
+            reason: "the journal has other columns, and cannot be appended \
+                     onto"
+                .into(),
+        })
+    );
+    assert_eq!(journal, older);
+}
+
+#[test]
+fn replay() {
+    let mut engine = Engine::default();
+    engine.process_reader(SRC.as_bytes(), |_| Ok(())).unwrap();
+    let mut replayed = Engine::default();
+    replayed.replay(JOURNAL.as_bytes()).unwrap();
+
+    assert_eq!(write(&replayed), write(&engine));
+}
+
+#[test]
+fn replay_parallel_journal() {
+    let src = "type, client, tx, amount\n\
+               deposit, 1, 1, 2\n\
+               deposit, 2, 2, 3\n\
+               deposit, 3, 3, 4\n\
+               withdrawal, 3, 4, 1\n\
+               dispute, 2, 2,\n\
+               chargeback, 2, 2,\n\
+               dispute, 3, 3,\n\
+               resolve, 3, 3,\n";
+    let path = journaled("replay_parallel_journal", src, Some(3));
+    let mut engine = Engine::default();
+    engine.process_reader(src.as_bytes(), |_| Ok(())).unwrap();
+    let mut replayed = Engine::default();
+    replayed.replay(fs::File::open(&path).unwrap()).unwrap();
+    fs::remove_file(path).unwrap();
+
+    assert_eq!(write(&replayed), write(&engine));
+}
+
+#[test]
+fn replay_diverging_journal() {
+    let journal = JOURNAL.replace("-0.5000", "-0.2500");
+    let mut engine = Engine::default();
+
+    assert_eq!(
+        engine.replay(journal.as_bytes()),
+        Err(EngineError::InvalidJournal {
+            line: 4,
+            reason: "the balances differ from the journal".into(),
+        })
+    );
+}
+
+#[test]
+fn replay_transfers() {
+    let src = "type, client, tx, amount, to\n\
+               deposit, 1, 1, 5, \n\
+               transfer, 1, 2, 2, 2\n\
+               dispute, 1, 2, ,\n\
+               chargeback, 1, 2, ,\n";
+    let path = journaled("replay_transfers", src, None);
+    let mut engine = Engine::default();
+    engine.process_reader(src.as_bytes(), |_| Ok(())).unwrap();
+    let mut replayed = Engine::default();
+    replayed.replay(fs::File::open(&path).unwrap()).unwrap();
+    let journal = fs::read_to_string(&path).unwrap();
+    fs::remove_file(path).unwrap();
+
+    // The changes to the destination are recorded as well, including for the
+    // dispute and chargeback of the transfer.
+    assert!(journal.contains(
+        "transfer,1,2,2.0000,-2.0000,0.0000,false,2,,2.0000,0.0000,false\n\
+         dispute,1,2,,0.0000,0.0000,false,,,-2.0000,2.0000,false\n\
+         chargeback,1,2,,2.0000,0.0000,false,,,0.0000,-2.0000,true\n"
+    ));
+    assert_eq!(write(&replayed), write(&engine));
+
+    let diverging = journal.replace(",,,-2.0000,2.0000,", ",,,-1.0000,1.0000,");
+    assert_eq!(
+        Engine::default().replay(diverging.as_bytes()),
+        Err(EngineError::InvalidJournal {
+            line: 4,
+            reason: "the balances differ from the journal".into(),
+        })
+    );
+}
+
+#[test]
+fn replay_account_actions() {
+    let src = "type, client, tx, amount, reason\n\
+               deposit, 1, 1, 5,\n\
+               dispute, 1, 1,,\n\
+               chargeback, 1, 1,,\n\
+               unfreeze, 1, 2,, false_positive\n\
+               close, 1, 3,, customer_request\n";
+    let path = journaled("replay_account_actions", src, None);
+    let mut engine = Engine::default();
+    engine.process_reader(src.as_bytes(), |_| Ok(())).unwrap();
+    let mut replayed = Engine::default();
+    replayed.replay(fs::File::open(&path).unwrap()).unwrap();
+    let journal = fs::read_to_string(&path).unwrap();
+    fs::remove_file(path).unwrap();
+
+    assert!(journal.contains(
+        "unfreeze,1,2,,0.0000,0.0000,false,,false_positive,,,\n\
+         close,1,3,,0.0000,0.0000,true,,customer_request,,,\n"
+    ));
+    assert_eq!(write(&replayed), write(&engine));
+}
+
+#[test]
+fn replay_refused_entry() {
+    let journal = "type,client,tx,amount,available,held,locked,to,reason,\
+                   to_available,to_held,to_locked\n\
+                   withdrawal,1,1,1.0000,-1.0000,0.0000,false,,,,,\n";
+    let mut engine = Engine::default();
+
+    assert_eq!(
+        engine.replay(journal.as_bytes()),
+        Err(EngineError::InvalidJournal {
+            line: 2,
+            reason: EngineError::InsufficientFunds { client: 1 }.to_string(),
+        })
+    );
+}
@@ -1,22 +1,234 @@
+use std::io;
+use std::net::TcpListener;
+use std::num::NonZeroUsize;
+use std::path::Path;
 use std::path::PathBuf;
 
 use crate::engine::core::Core;
+use crate::engine::core::Policy;
+use crate::engine::deserializer::InputFormat;
+use crate::engine::journal::Journal;
+use crate::engine::serializer::Format;
+use crate::engine::statement::StatementEntry;
+use crate::error::Reject;
+use crate::types::ClientId;
 use crate::types::EngineResult;
 
-mod core;
-mod deserializer;
-mod serializer;
-
-pub fn run<P>(src: P) -> EngineResult<()>
-where
-    P: Into<PathBuf>,
-{
-    let mut core = Core::default();
-    deserializer::deserialize(src, |transaction| {
-        core.process(transaction).ok();
-    })
-    .ok();
-    let clients = core.clients();
-    serializer::serialize(clients);
-    Ok(())
+pub mod core;
+pub mod deserializer;
+pub mod journal;
+mod parallel;
+pub mod serializer;
+mod server;
+pub mod statement;
+#[cfg(test)]
+mod tests;
+pub mod validation;
+
+/// The entire engine: a [`Core`] which is fed by the deserializer and drained
+/// by the serializer.
+///
+/// Transactions can be streamed in from several sources, one after another;
+/// they are all applied onto the same set of clients.
+#[derive(Default)]
+pub struct Engine {
+    core: Core,
+    /// The format of every source; if unset, the format of a file is implied
+    /// by its extension, and readers are expected to hold CSV.
+    input_format: Option<InputFormat>,
+}
+
+impl Engine {
+    pub fn new(policy: Policy) -> Self {
+        Self {
+            core: Core::new(policy),
+            input_format: None,
+        }
+    }
+
+    /// Builds an engine around an existing `core` (e.g., one backed by a
+    /// [`FileStore`](crate::engine::core::store::FileStore)).
+    pub fn with_core(core: Core) -> Self {
+        Self {
+            core,
+            input_format: None,
+        }
+    }
+
+    pub fn core(&self) -> &Core {
+        &self.core
+    }
+
+    /// Reads every source from now on as `input_format`, whatever its
+    /// extension.
+    pub fn set_input_format(&mut self, input_format: InputFormat) {
+        self.input_format = Some(input_format);
+    }
+
+    /// Records every transaction accepted from now on into `journal`, which
+    /// is flushed after each entry.
+    pub fn set_journal(&mut self, journal: Journal) {
+        self.core.set_journal(journal);
+    }
+
+    /// Processes every record of the file at `src` (or of `stdin`, if `src` is
+    /// `-`), handing each refused record to `reject`.
+    pub fn process_path<P, R>(&mut self, src: P, reject: R) -> EngineResult<()>
+    where
+        P: Into<PathBuf>,
+        R: FnMut(Reject) -> EngineResult<()>,
+    {
+        let src = src.into();
+        let format = self.path_format(&src);
+        let core = &mut self.core;
+        let result = deserializer::deserialize_reader(
+            deserializer::open(src)?,
+            format,
+            |transaction| core.process(transaction),
+            reject,
+        );
+        result.and(self.core.flush_journal())
+    }
+
+    /// Processes every record read from `src`, handing each refused record to
+    /// `reject`.
+    pub fn process_reader<S, R>(
+        &mut self,
+        src: S,
+        reject: R,
+    ) -> EngineResult<()>
+    where
+        S: io::Read,
+        R: FnMut(Reject) -> EngineResult<()>,
+    {
+        let format = self.input_format.unwrap_or_default();
+        let core = &mut self.core;
+        let result = deserializer::deserialize_reader(
+            src,
+            format,
+            |transaction| core.process(transaction),
+            reject,
+        );
+        result.and(self.core.flush_journal())
+    }
+
+    /// Processes every record of the file at `src` (or of `stdin`, if `src` is
+    /// `-`) across `threads` worker threads, sharded by client id.
+    ///
+    /// Refused records are handed to `reject` once all of `src` has been
+    /// processed, in the order of their line numbers.
+    pub fn process_path_parallel<P, R>(
+        &mut self,
+        src: P,
+        threads: NonZeroUsize,
+        reject: R,
+    ) -> EngineResult<()>
+    where
+        P: Into<PathBuf>,
+        R: FnMut(Reject) -> EngineResult<()>,
+    {
+        let src = src.into();
+        let format = self.path_format(&src);
+        let src = deserializer::open(src)?;
+        let result =
+            parallel::process(&mut self.core, src, format, threads, reject);
+        result.and(self.core.flush_journal())
+    }
+
+    /// Processes every record read from `src` across `threads` worker threads,
+    /// sharded by client id.
+    ///
+    /// Refused records are handed to `reject` once all of `src` has been
+    /// processed, in the order of their line numbers.
+    pub fn process_reader_parallel<S, R>(
+        &mut self,
+        src: S,
+        threads: NonZeroUsize,
+        reject: R,
+    ) -> EngineResult<()>
+    where
+        S: io::Read,
+        R: FnMut(Reject) -> EngineResult<()>,
+    {
+        let format = self.input_format.unwrap_or_default();
+        let result =
+            parallel::process(&mut self.core, src, format, threads, reject);
+        result.and(self.core.flush_journal())
+    }
+
+    /// Processes every record read from `src`, returning the statement of
+    /// `client`: every transaction which referred to it, along with its
+    /// balances after each of them.
+    pub fn statement<S, R>(
+        &mut self,
+        src: S,
+        client: ClientId,
+        reject: R,
+    ) -> EngineResult<Vec<StatementEntry>>
+    where
+        S: io::Read,
+        R: FnMut(Reject) -> EngineResult<()>,
+    {
+        let format = self.input_format.unwrap_or_default();
+        let result =
+            statement::process(&mut self.core, src, format, client, reject);
+        self.core.flush_journal().and(result)
+    }
+
+    /// Replays every entry of the journal read from `src` (as written through
+    /// [`Engine::set_journal`]) onto this engine.
+    pub fn replay<S>(&mut self, src: S) -> EngineResult<()>
+    where
+        S: io::Read,
+    {
+        journal::replay(&mut self.core, src)
+    }
+
+    /// Serves transactions and balance queries submitted over `listener`
+    /// forever (see the [`server`] module for the protocol).
+    ///
+    /// Only returns if accepting a connection fails.
+    pub fn serve(self, listener: TcpListener) -> EngineResult<()> {
+        server::serve(self.core, listener)
+    }
+
+    /// Loads the snapshot read from `src` (as written by
+    /// [`Engine::snapshot`]) into this engine, which is expected to be fresh.
+    pub fn restore<S>(&mut self, src: S) -> EngineResult<()>
+    where
+        S: io::Read,
+    {
+        self.core.restore(src)
+    }
+
+    /// Writes a snapshot of every client and transaction into `dst`, so that
+    /// processing can later pick up from where it was left off.
+    pub fn snapshot<W>(&self, dst: W) -> EngineResult<()>
+    where
+        W: io::Write,
+    {
+        self.core.snapshot(dst)
+    }
+
+    /// Writes the current state of every client into `dst`, as CSV.
+    pub fn write<W>(&self, dst: W) -> EngineResult<()>
+    where
+        W: io::Write,
+    {
+        self.write_as(dst, Format::Csv)
+    }
+
+    /// Writes the current state of every client into `dst`, in the given
+    /// `format`.
+    pub fn write_as<W>(&self, dst: W, format: Format) -> EngineResult<()>
+    where
+        W: io::Write,
+    {
+        serializer::serialize(dst, self.core.clients(), format)
+    }
+
+    fn path_format(&self, src: &Path) -> InputFormat {
+        self.input_format
+            .unwrap_or_else(|| InputFormat::detect(src))
+    }
 }
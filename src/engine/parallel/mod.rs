@@ -0,0 +1,374 @@
+//! Multi-threaded processing, sharded by client id.
+//!
+//! The calling thread deserializes the input and routes each transaction to
+//! one of several worker threads, each of which owns its own [`Core`]. Since a
+//! client always maps onto the same worker, and each worker processes its
+//! transactions in the order in which they were read, the per-client ordering
+//! of the input is preserved.
+//!
+//! Transaction ids must be unique across all clients, so the router keeps
+//! track of which client each charge was routed for:
+//! - A charge whose id was already routed is checked against the worker of the
+//!   client it was routed for, once that worker is done with its pending
+//!   transactions: it is refused as a duplicate if that worker stored the
+//!   original charge, and routed as usual (taking the id over) if the original
+//!   charge was itself refused, just like in sequential processing.
+//! - A dispute, resolve or chargeback is routed to the worker of the client
+//!   owning the referenced transaction, so that the same checks as in
+//!   sequential processing (e.g., `ClientMismatch`) are performed.
+//!
+//! A transfer (or a dispute, resolve or chargeback of one) involves two
+//! clients, which may belong to different workers. The worker of the
+//! destination then lends it to the worker of the source, which applies the
+//! transaction onto both of them at once before handing the destination back.
+//! The router waits for the destination to be handed back before routing any
+//! further transaction, so the per-client ordering is still preserved.
+//!
+//! The owner of every routed charge is kept in memory, so unlike with a
+//! single [`Core`] over a `FileStore`, memory usage grows with the number of
+//! charges.
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::BTreeMap;
+use std::io;
+use std::mem;
+use std::num::NonZeroUsize;
+use std::ops::Range;
+use std::sync::mpsc;
+use std::thread;
+
+use crate::client::Client;
+use crate::engine::core::Core;
+use crate::engine::deserializer;
+use crate::engine::deserializer::InputFormat;
+use crate::engine::deserializer::Origin;
+use crate::error::EngineError;
+use crate::error::Reject;
+use crate::transaction::AccountAction;
+use crate::transaction::Charge;
+use crate::transaction::ChargeRef;
+use crate::transaction::Transaction;
+use crate::transaction::Transfer;
+use crate::types::ClientId;
+use crate::types::EngineResult;
+use crate::types::TransactionId;
+
+/// The number of transactions sent to a worker at once.
+const BATCH_SIZE: usize = 1024;
+
+/// The number of batches which can be queued up for a single worker.
+const QUEUE_SIZE: usize = 16;
+
+/// A unit of work for a single worker.
+enum Message {
+    Batch(Batch),
+    /// Removes `client` from the worker's core, and hands it over to `reply`
+    /// (or `None`, if the core does not hold it).
+    Lend {
+        client: ClientId,
+        reply: mpsc::Sender<Option<Client>>,
+    },
+    /// Processes `batch` while hosting `guest`, which was lent by another
+    /// worker (as `lent`) and is then handed over to `reply`.
+    Host {
+        batch: Batch,
+        guest: ClientId,
+        lent: Option<Client>,
+        reply: mpsc::Sender<Option<Client>>,
+    },
+    /// Gives back a client which was lent.
+    Return(Client),
+    /// Tells `reply` whether the worker's core stores the charge `tx`.
+    Stored {
+        tx: TransactionId,
+        reply: mpsc::Sender<EngineResult<bool>>,
+    },
+}
+
+/// The worker which must process a transaction: the one of `client`, which
+/// must also host `guest` if the transaction involves a client of another
+/// worker.
+struct Route {
+    client: ClientId,
+    guest: Option<ClientId>,
+}
+
+/// A group of transactions destined for the same worker.
+///
+/// The original text of every record is appended onto a single buffer, so that
+/// a batch only performs a handful of allocations.
+#[derive(Default)]
+struct Batch {
+    entries: Vec<BatchEntry>,
+    records: String,
+}
+
+struct BatchEntry {
+    transaction: Transaction,
+    line: u64,
+    record: Range<usize>,
+}
+
+impl Batch {
+    fn push(&mut self, transaction: Transaction, origin: &Origin) {
+        let start = self.records.len();
+        origin.write_record(&mut self.records);
+        let end = self.records.len();
+        self.entries.push(BatchEntry {
+            transaction,
+            line: origin.line,
+            record: start..end,
+        });
+    }
+
+    fn reject(
+        &self,
+        line: u64,
+        record: Range<usize>,
+        reason: EngineError,
+    ) -> Reject {
+        let record = self.records.get(record).unwrap_or_default().into();
+        Reject {
+            line,
+            record,
+            reason,
+        }
+    }
+}
+
+fn shard_of(client: ClientId, shards: usize) -> usize {
+    usize::from(client) % shards
+}
+
+/// Processes every record read from `src` onto `core`, using `shards` worker
+/// threads.
+///
+/// Refused records are handed to `reject` once all of the input has been
+/// processed, in the order of their line numbers.
+pub(crate) fn process<S, R>(
+    core: &mut Core,
+    src: S,
+    format: InputFormat,
+    shards: NonZeroUsize,
+    reject: R,
+) -> EngineResult<()>
+where
+    S: io::Read,
+    R: FnMut(Reject) -> EngineResult<()>,
+{
+    let shards = shards.get();
+    let cores = core.split(shards, |client| shard_of(client, shards))?;
+    let mut owners = BTreeMap::new();
+    for shard_core in &cores {
+        owners.append(&mut shard_core.owners()?);
+    }
+    let mut rejects = vec![];
+    let (result, outcomes) = thread::scope(|scope| {
+        let (senders, handles): (Vec<_>, Vec<_>) = cores
+            .into_iter()
+            .map(|core| {
+                let (sender, receiver) = mpsc::sync_channel(QUEUE_SIZE);
+                let handle = scope.spawn(move || work(core, receiver));
+                (sender, handle)
+            })
+            .unzip();
+        let mut batches =
+            (0..shards).map(|_| Batch::default()).collect::<Vec<_>>();
+        let send = |shard: usize, message: Message| {
+            senders[shard]
+                .send(message)
+                .map_err(|_| EngineError::ShardUnavailable { shard })
+        };
+        let result = deserializer::deserialize_reader_with_origins(
+            src,
+            format,
+            |transaction, origin| {
+                // The pending transactions of the worker must be processed
+                // before it is asked about one of them.
+                let stored = |shard: usize, tx| {
+                    let pending = mem::take(&mut batches[shard]);
+                    send(shard, Message::Batch(pending))?;
+                    let (reply, replies) = mpsc::channel();
+                    send(shard, Message::Stored { tx, reply })?;
+                    replies
+                        .recv()
+                        .map_err(|_| EngineError::ShardUnavailable { shard })?
+                };
+                let Route { client, guest } =
+                    route(&mut owners, &transaction, shards, stored)?;
+                let shard = shard_of(client, shards);
+                batches[shard].push(transaction, origin);
+                let Some(guest) = guest else {
+                    let batch = &mut batches[shard];
+                    return match batch.entries.len() < BATCH_SIZE {
+                        true => Ok(()),
+                        false => send(shard, Message::Batch(mem::take(batch))),
+                    };
+                };
+                // The pending transactions of the guest must be processed
+                // before it is lent.
+                let guest_shard = shard_of(guest, shards);
+                let pending = mem::take(&mut batches[guest_shard]);
+                send(guest_shard, Message::Batch(pending))?;
+                let (reply, replies) = mpsc::channel();
+                send(guest_shard, Message::Lend {
+                    client: guest,
+                    reply: reply.clone(),
+                })?;
+                let lent = replies.recv().map_err(|_| {
+                    EngineError::ShardUnavailable { shard: guest_shard }
+                })?;
+                send(shard, Message::Host {
+                    batch: mem::take(&mut batches[shard]),
+                    guest,
+                    lent,
+                    reply,
+                })?;
+                let returned = replies
+                    .recv()
+                    .map_err(|_| EngineError::ShardUnavailable { shard })?;
+                match returned {
+                    Some(client) => send(guest_shard, Message::Return(client)),
+                    None => Ok(()),
+                }
+            },
+            |reject| match reject.reason {
+                EngineError::ShardUnavailable { .. } => Err(reject.reason),
+                _ => {
+                    rejects.push(reject);
+                    Ok(())
+                },
+            },
+        )
+        .and_then(|()| {
+            mem::take(&mut batches)
+                .into_iter()
+                .enumerate()
+                .try_for_each(|(shard, batch)| {
+                    send(shard, Message::Batch(batch))
+                })
+        });
+        drop(senders);
+        let outcomes = handles
+            .into_iter()
+            .enumerate()
+            .map(|(shard, handle)| {
+                handle
+                    .join()
+                    .map_err(|_| EngineError::ShardUnavailable { shard })
+            })
+            .collect::<Vec<_>>();
+        (result, outcomes)
+    });
+    let mut failure = result.err();
+    for outcome in outcomes {
+        let merged = outcome.and_then(|(shard_core, shard_rejects)| {
+            rejects.extend(shard_rejects);
+            core.merge(shard_core)
+        });
+        if let Err(error) = merged {
+            failure = failure.or(Some(error));
+        };
+    }
+    if let Some(error) = failure {
+        return Err(error);
+    };
+    rejects.sort_by_key(|reject| reject.line);
+    rejects.into_iter().try_for_each(reject)
+}
+
+/// Returns the route of `transaction`.
+///
+/// `stored(shard, tx)` tells whether the worker `shard` stores the charge
+/// `tx`; it is only asked when the id of a charge was already routed.
+fn route<F>(
+    owners: &mut BTreeMap<TransactionId, (ClientId, Option<ClientId>)>,
+    transaction: &Transaction,
+    shards: usize,
+    mut stored: F,
+) -> EngineResult<Route>
+where
+    F: FnMut(usize, TransactionId) -> EngineResult<bool>,
+{
+    let same_shard = |a, b| shard_of(a, shards) == shard_of(b, shards);
+    let mut claim = |client: ClientId, tx: TransactionId, to| {
+        if let Some((owner, _)) = owners.get(&tx) {
+            if stored(shard_of(*owner, shards), tx)? {
+                return Err(EngineError::DuplicateTx { tx });
+            };
+        };
+        owners.insert(tx, (client, to));
+        Ok((client, to))
+    };
+    let (client, to) = match transaction {
+        Transaction::Deposit(Charge { client, tx, .. })
+        | Transaction::Withdrawal(Charge { client, tx, .. }) => {
+            claim(*client, *tx, None)?
+        },
+        Transaction::Transfer(Transfer {
+            charge: Charge { client, tx, .. },
+            to,
+        }) => claim(*client, *tx, Some(*to))?,
+        Transaction::Dispute(ChargeRef { client, tx, .. })
+        | Transaction::Resolve(ChargeRef { client, tx, .. })
+        | Transaction::Chargeback(ChargeRef { client, tx, .. }) => {
+            owners.get(tx).copied().unwrap_or((*client, None))
+        },
+        Transaction::Freeze(AccountAction { client, .. })
+        | Transaction::Unfreeze(AccountAction { client, .. })
+        | Transaction::Close(AccountAction { client, .. }) => (*client, None),
+    };
+    let guest = to.filter(|to| !same_shard(*to, client));
+    Ok(Route { client, guest })
+}
+
+fn work(
+    mut core: Core,
+    receiver: mpsc::Receiver<Message>,
+) -> (Core, Vec<Reject>) {
+    let mut rejects = vec![];
+    for message in receiver {
+        match message {
+            Message::Batch(batch) => {
+                process_batch(&mut core, batch, &mut rejects)
+            },
+            Message::Lend { client, reply } => {
+                reply.send(core.take_client(client)).ok();
+            },
+            Message::Host {
+                batch,
+                guest,
+                lent,
+                reply,
+            } => {
+                if let Some(lent) = lent {
+                    core.insert_client(lent);
+                };
+                process_batch(&mut core, batch, &mut rejects);
+                reply.send(core.take_client(guest)).ok();
+            },
+            Message::Return(client) => core.insert_client(client),
+            Message::Stored { tx, reply } => {
+                let stored = core.transaction(tx).map(|tx| tx.is_some());
+                reply.send(stored).ok();
+            },
+        };
+    }
+    (core, rejects)
+}
+
+fn process_batch(core: &mut Core, mut batch: Batch, rejects: &mut Vec<Reject>) {
+    let entries = mem::take(&mut batch.entries);
+    for BatchEntry {
+        transaction,
+        line,
+        record,
+    } in entries
+    {
+        if let Err(reason) = core.process(transaction) {
+            rejects.push(batch.reject(line, record, reason));
+        };
+    }
+}
@@ -0,0 +1,170 @@
+use std::fmt::Write;
+use std::num::NonZeroUsize;
+
+use crate::engine::Engine;
+use crate::engine::Policy;
+use crate::error::EngineError;
+use crate::error::Reject;
+
+/// Generates a deterministic mix of every transaction type over `clients`
+/// clients, including references to transactions of other clients.
+///
+/// If `reuse` is set, some charges reuse the id of an earlier transaction
+/// (which may or may not have been accepted).
+fn generate(rows: u32, clients: u32, reuse: bool) -> String {
+    let mut seed = 0x2545_f491_u64;
+    let mut next = |bound: u32| {
+        seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1);
+        u32::try_from((seed >> 33) % u64::from(bound)).unwrap()
+    };
+    let mut src = String::from("type, client, tx, amount, to\n");
+    for tx in 1..=rows {
+        let client = next(clients);
+        let referenced = next(tx) + 1;
+        let amount = format!("{}.{:04}", next(100), next(10_000));
+        let tx = match reuse && next(4) == 0 {
+            true => referenced,
+            false => tx,
+        };
+        let row = match next(12) {
+            0..=3 => format!("deposit, {}, {}, {},", client, tx, amount),
+            4..=5 => format!("withdrawal, {}, {}, {},", client, tx, amount),
+            6..=7 => format!("dispute, {}, {},,", client, referenced),
+            8 => format!("resolve, {}, {},,", client, referenced),
+            9 => format!("chargeback, {}, {},,", client, referenced),
+            _ => {
+                let to = next(clients);
+                format!("transfer, {}, {}, {}, {}", client, tx, amount, to)
+            },
+        };
+        writeln!(src, "{}", row).unwrap();
+    }
+    src
+}
+
+fn run(
+    src: &str,
+    threads: Option<usize>,
+    policy: Policy,
+) -> (String, Vec<Reject>) {
+    let mut engine = Engine::new(policy);
+    let mut rejects = vec![];
+    let reject = |reject| {
+        rejects.push(reject);
+        Ok(())
+    };
+    match threads.and_then(NonZeroUsize::new) {
+        Some(threads) => engine
+            .process_reader_parallel(src.as_bytes(), threads, reject)
+            .unwrap(),
+        None => engine.process_reader(src.as_bytes(), reject).unwrap(),
+    };
+    let mut dst = vec![];
+    engine.write(&mut dst).unwrap();
+    (String::from_utf8(dst).unwrap(), rejects)
+}
+
+#[test]
+fn matches_sequential_processing() {
+    let src = generate(20_000, 64, false);
+    let policy = Policy {
+        allow_redispute: true,
+        dispute_withdrawals: true,
+        ..Default::default()
+    };
+    let expected = run(&src, None, policy.clone());
+    for threads in [1, 2, 3, 8] {
+        assert_eq!(run(&src, Some(threads), policy.clone()), expected);
+    }
+}
+
+#[test]
+fn reused_ids_match_sequential_processing() {
+    // The withdrawal is refused, so its id is free for the deposit.
+    let src = "type, client, tx, amount\n\
+               withdrawal, 1, 5, 10\n\
+               deposit, 2, 5, 3\n";
+    let expected = run(src, None, Policy::default());
+    assert!(expected.0.contains("2,3.0000,0.0000,3.0000,false,active"));
+    assert_eq!(run(src, Some(2), Policy::default()), expected);
+
+    let src = generate(20_000, 64, true);
+    let policy = Policy {
+        allow_redispute: true,
+        dispute_withdrawals: true,
+        ..Default::default()
+    };
+    let expected = run(&src, None, policy.clone());
+    for threads in [1, 2, 3, 8] {
+        assert_eq!(run(&src, Some(threads), policy.clone()), expected);
+    }
+}
+
+#[test]
+fn cross_shard_client_mismatch() {
+    let src = "type, client, tx, amount\n\
+               deposit, 1, 1, 1\n\
+               deposit, 2, 2, 1\n\
+               dispute, 2, 1,\n";
+    let (accounts, rejects) = run(src, Some(2), Policy::default());
+    assert_eq!(
+        accounts,
+        "client,available,held,total,locked,account_status\n\
+         1,1.0000,0.0000,1.0000,false,active\n\
+         2,1.0000,0.0000,1.0000,false,active\n"
+    );
+    assert_eq!(rejects, vec![Reject {
+        line: 4,
+        record: "dispute, 2, 1,".into(),
+        reason: EngineError::ClientMismatch {
+            client: 2,
+            tx: 1,
+            owner: 1
+        },
+    }]);
+}
+
+#[test]
+fn cross_shard_duplicate() {
+    let src = "type, client, tx, amount\n\
+               deposit, 1, 1, 1\n\
+               deposit, 2, 1, 1\n";
+    let (_, rejects) = run(src, Some(2), Policy::default());
+    assert_eq!(
+        rejects
+            .into_iter()
+            .map(|Reject { line, reason, .. }| (line, reason))
+            .collect::<Vec<_>>(),
+        vec![(3, EngineError::DuplicateTx { tx: 1 })]
+    );
+}
+
+#[test]
+fn preserves_existing_state() {
+    let mut engine = Engine::default();
+    let src = "type, client, tx, amount\n\
+               deposit, 1, 1, 1\n\
+               deposit, 2, 2, 2\n";
+    engine.process_reader(src.as_bytes(), |_| Ok(())).unwrap();
+    let src = "type, client, tx, amount\n\
+               dispute, 1, 1,\n\
+               deposit, 3, 2, 3\n\
+               withdrawal, 2, 3, 0.5\n";
+    let mut rejects = vec![];
+    let threads = NonZeroUsize::new(4).unwrap();
+    engine
+        .process_reader_parallel(src.as_bytes(), threads, |reject| {
+            rejects.push(reject.reason);
+            Ok(())
+        })
+        .unwrap();
+    let mut dst = vec![];
+    engine.write(&mut dst).unwrap();
+    assert_eq!(rejects, vec![EngineError::DuplicateTx { tx: 2 }]);
+    assert_eq!(
+        String::from_utf8(dst).unwrap(),
+        "client,available,held,total,locked,account_status\n\
+         1,0.0000,1.0000,1.0000,false,active\n\
+         2,1.5000,0.0000,1.5000,false,active\n"
+    );
+}
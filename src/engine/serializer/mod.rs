@@ -1,18 +1,62 @@
+#[cfg(test)]
+mod tests;
+
+use std::fs::File;
 use std::io;
+use std::io::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
 
 use serde::Serialize;
 
 use crate::client::Client;
+use crate::client::Status;
+use crate::engine::statement::StatementEntry;
+use crate::error::EngineError;
+use crate::error::Reject;
 use crate::types::Amount;
 use crate::types::ClientId;
+use crate::types::EngineResult;
+use crate::types::TransactionId;
+
+/// The format in which an output is written.
+///
+/// Amounts are always written as strings (e.g., `"1.5000"`), so that JSON
+/// consumers do not read them back through a lossy float.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Format {
+    #[default]
+    Csv,
+    /// A single (pretty-printed) JSON array.
+    Json,
+    /// One JSON object per line.
+    Jsonl,
+}
+
+impl FromStr for Format {
+    type Err = &'static str;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        match src {
+            "csv" => Ok(Self::Csv),
+            "json" => Ok(Self::Json),
+            "jsonl" => Ok(Self::Jsonl),
+            _ => {
+                Err("Oops, the output format must be either `csv`, `json` or \
+                 `jsonl`.")
+            },
+        }
+    }
+}
 
 #[derive(Serialize)]
-struct RawClient {
+pub(crate) struct RawClient {
     client: ClientId,
     available: Amount,
     held: Amount,
     total: Amount,
     locked: bool,
+    account_status: Status,
 }
 
 impl From<&Client> for RawClient {
@@ -21,6 +65,7 @@ impl From<&Client> for RawClient {
         let held = client.held();
         let total = client.total();
         let locked = client.locked();
+        let account_status = client.status();
         let client = client.id();
         RawClient {
             client,
@@ -28,15 +73,154 @@ impl From<&Client> for RawClient {
             held,
             total,
             locked,
+            account_status,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct RawReject<'a> {
+    line: u64,
+    record: &'a str,
+    reason: String,
+}
+
+impl<'a> From<&'a Reject> for RawReject<'a> {
+    fn from(reject: &'a Reject) -> Self {
+        RawReject {
+            line: reject.line,
+            record: &reject.record,
+            reason: reject.reason.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct RawStatementEntry<'a> {
+    line: u64,
+    r#type: &'static str,
+    tx: TransactionId,
+    amount: Option<Amount>,
+    reason: Option<&'a str>,
+    available: Amount,
+    held: Amount,
+    total: Amount,
+    locked: bool,
+    account_status: Status,
+    refusal: Option<String>,
+}
+
+impl<'a> From<&'a StatementEntry> for RawStatementEntry<'a> {
+    fn from(entry: &'a StatementEntry) -> Self {
+        let transaction = &entry.transaction;
+        RawStatementEntry {
+            line: entry.line,
+            r#type: transaction.type_name(),
+            tx: transaction.tx(),
+            amount: entry.amount,
+            reason: transaction.reason(),
+            available: entry.available,
+            held: entry.held,
+            total: entry.total,
+            locked: entry.status.locked(),
+            account_status: entry.status,
+            refusal: entry.refusal.as_ref().map(ToString::to_string),
         }
     }
 }
 
-pub fn serialize(clients: Vec<&Client>) {
-    let stdout = io::stdout();
-    let mut writer = csv::Writer::from_writer(stdout);
-    clients.into_iter().for_each(|client| {
-        let raw_client = client.into();
-        writer.serialize::<RawClient>(raw_client).ok();
-    });
+/// Writes `clients` into `dst` in the given `format`, failing on the first I/O
+/// error.
+pub fn serialize<W>(
+    dst: W,
+    clients: Vec<&Client>,
+    format: Format,
+) -> EngineResult<()>
+where
+    W: io::Write,
+{
+    let clients = clients.into_iter().map(RawClient::from);
+    serialize_records(dst, clients, format)
+}
+
+/// Writes the `entries` of a statement into `dst`, in the given `format`.
+pub fn serialize_statement<W>(
+    dst: W,
+    entries: &[StatementEntry],
+    format: Format,
+) -> EngineResult<()>
+where
+    W: io::Write,
+{
+    let entries = entries.iter().map(RawStatementEntry::from);
+    serialize_records(dst, entries, format)
+}
+
+fn serialize_records<W, I>(
+    mut dst: W,
+    records: I,
+    format: Format,
+) -> EngineResult<()>
+where
+    W: io::Write,
+    I: Iterator,
+    I::Item: Serialize,
+{
+    match format {
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(dst);
+            for record in records {
+                writer.serialize(record).map_err(to_io_error)?;
+            }
+            writer.flush()?;
+        },
+        Format::Json => {
+            let records = records.collect::<Vec<_>>();
+            serde_json::to_writer_pretty(&mut dst, &records)
+                .map_err(json_to_io_error)?;
+            writeln!(dst)?;
+            dst.flush()?;
+        },
+        Format::Jsonl => {
+            let mut dst = io::BufWriter::new(dst);
+            for record in records {
+                serde_json::to_writer(&mut dst, &record)
+                    .map_err(json_to_io_error)?;
+                writeln!(dst)?;
+            }
+            dst.flush()?;
+        },
+    };
+    Ok(())
+}
+
+pub fn rejects_writer<P>(dst: P) -> EngineResult<csv::Writer<File>>
+where
+    P: Into<PathBuf>,
+{
+    csv::Writer::from_path(dst.into()).map_err(to_io_error)
+}
+
+pub fn serialize_reject<W>(
+    writer: &mut csv::Writer<W>,
+    reject: &Reject,
+) -> EngineResult<()>
+where
+    W: io::Write,
+{
+    writer
+        .serialize::<RawReject>(reject.into())
+        .map_err(to_io_error)
+}
+
+fn to_io_error(error: csv::Error) -> EngineError {
+    EngineError::Io {
+        reason: error.to_string(),
+    }
+}
+
+fn json_to_io_error(error: serde_json::Error) -> EngineError {
+    EngineError::Io {
+        reason: error.to_string(),
+    }
 }
@@ -0,0 +1,147 @@
+use std::io;
+
+use crate::error::EngineError;
+
+struct FullDisk;
+
+impl io::Write for FullDisk {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        Err(io::Error::other("No space left on device"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn serialize() {
+    let clients = [client!([1, 1.5, 0.0, false]), client!([2, 0.1, 0.2, true])];
+    let mut dst = vec![];
+    super::serialize(&mut dst, clients.iter().collect(), super::Format::Csv)
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(dst).unwrap(),
+        "client,available,held,total,locked,account_status\n\
+         1,1.5000,0.0000,1.5000,false,active\n\
+         2,0.1000,0.2000,0.3000,true,frozen\n"
+    );
+}
+
+#[test]
+fn serialize_json() {
+    let clients = [client!([1, 1.5, 0.0, false]), client!([2, 0.1, 0.2, true])];
+    let mut dst = vec![];
+    super::serialize(&mut dst, clients.iter().collect(), super::Format::Json)
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(dst).unwrap(),
+        r#"[
+  {
+    "client": 1,
+    "available": "1.5000",
+    "held": "0.0000",
+    "total": "1.5000",
+    "locked": false,
+    "account_status": "active"
+  },
+  {
+    "client": 2,
+    "available": "0.1000",
+    "held": "0.2000",
+    "total": "0.3000",
+    "locked": true,
+    "account_status": "frozen"
+  }
+]
+"#
+    );
+}
+
+#[test]
+fn serialize_jsonl() {
+    let clients = [client!([1, 1.5, 0.0, false]), client!([2, 0.1, 0.2, true])];
+    let mut dst = vec![];
+    super::serialize(&mut dst, clients.iter().collect(), super::Format::Jsonl)
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(dst).unwrap(),
+        "{\"client\":1,\"available\":\"1.5000\",\"held\":\"0.0000\",\
+         \"total\":\"1.5000\",\"locked\":false,\"account_status\":\"active\"}\n\
+         {\"client\":2,\"available\":\"0.1000\",\"held\":\"0.2000\",\
+         \"total\":\"0.3000\",\"locked\":true,\"account_status\":\"frozen\"}\n"
+    );
+}
+
+#[test]
+fn serialize_empty_json() {
+    let mut dst = vec![];
+    super::serialize(&mut dst, vec![], super::Format::Json).unwrap();
+    assert_eq!(String::from_utf8(dst).unwrap(), "[]\n");
+}
+
+#[test]
+fn serialize_io_error() {
+    let clients = [client!([1, 1.5, 0.0, false])];
+    let result = super::serialize(
+        FullDisk,
+        clients.iter().collect(),
+        super::Format::Json,
+    );
+    assert!(matches!(result, Err(EngineError::Io { .. })));
+}
+
+fn statement() -> Vec<crate::engine::statement::StatementEntry> {
+    let src = "type, client, tx, amount, reason\n\
+               deposit, 1, 1, 2,\n\
+               withdrawal, 1, 2, 5,\n\
+               dispute, 1, 1,,\n\
+               freeze, 1, 3,, manual_review\n";
+    let mut engine = crate::Engine::default();
+    engine.statement(src.as_bytes(), 1, |_| Ok(())).unwrap()
+}
+
+#[test]
+fn serialize_statement_csv() {
+    let mut dst = vec![];
+    super::serialize_statement(&mut dst, &statement(), super::Format::Csv)
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(dst).unwrap(),
+        "line,type,tx,amount,reason,available,held,total,locked,account_status,refusal\n\
+         2,deposit,1,2.0000,,2.0000,0.0000,2.0000,false,active,\n\
+         3,withdrawal,2,5.0000,,2.0000,0.0000,2.0000,false,active,client 1 does not have enough available funds\n\
+         4,dispute,1,2.0000,,0.0000,2.0000,2.0000,false,active,\n\
+         5,freeze,3,,manual_review,0.0000,2.0000,2.0000,true,frozen,\n"
+    );
+}
+
+#[test]
+fn serialize_statement_json() {
+    let mut dst = vec![];
+    super::serialize_statement(
+        &mut dst,
+        &statement()[..1],
+        super::Format::Json,
+    )
+    .unwrap();
+    assert_eq!(
+        String::from_utf8(dst).unwrap(),
+        r#"[
+  {
+    "line": 2,
+    "type": "deposit",
+    "tx": 1,
+    "amount": "2.0000",
+    "reason": null,
+    "available": "2.0000",
+    "held": "0.0000",
+    "total": "2.0000",
+    "locked": false,
+    "account_status": "active",
+    "refusal": null
+  }
+]
+"#
+    );
+}
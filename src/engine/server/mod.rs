@@ -0,0 +1,245 @@
+//! A long-running server, which keeps a single [`Core`] in memory and applies
+//! transactions onto it as they are submitted over TCP.
+//!
+//! The protocol is line-based: each request is a single line, and is answered
+//! by a single line of JSON. A request is either a transaction, written as a
+//! CSV record without a header (`deposit, 1, 1, 2.5`) or as a JSON object
+//! (`{"type": "deposit", "client": 1, "tx": 1, "amount": "2.5"}`), or a
+//! balance query (`balance, 1` or `{"type": "balance", "client": 1}`).
+//! Blank lines are ignored, and lines longer than [`MAX_LINE`] bytes are
+//! refused as malformed without being kept in memory.
+//!
+//! Transactions are answered with `{"status":"accepted"}`, or with the class
+//! and the reason of their refusal
+//! (`{"status":"rejected","error":"insufficient_funds","reason":"..."}`).
+//! Balance queries are answered with the same fields as the accounts output
+//! (`{"status":"balance","client":1,"available":"2.5000",...}`).
+//!
+//! Every connection is served by its own thread, and transactions are applied
+//! one at a time, in the order in which they are received.
+
+#[cfg(test)]
+mod tests;
+
+use std::borrow::Cow;
+use std::io;
+use std::io::BufRead;
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+use std::thread;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::engine::core::Core;
+use crate::engine::deserializer;
+use crate::engine::deserializer::InputFormat;
+use crate::engine::serializer::RawClient;
+use crate::error::EngineError;
+use crate::error::ParseErrorKind;
+use crate::transaction::Transaction;
+use crate::types::ClientId;
+use crate::types::EngineResult;
+
+/// The `type` of a balance query.
+const BALANCE: &str = "balance";
+
+/// The length of the longest request, in bytes (including its newline).
+const MAX_LINE: usize = 64 * 1024;
+
+enum Request {
+    Transaction(Transaction),
+    Balance(ClientId),
+}
+
+#[derive(Deserialize)]
+struct RawQuery<'a> {
+    #[serde(borrow)]
+    r#type: Cow<'a, str>,
+    client: ClientId,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum Response {
+    Accepted,
+    Rejected {
+        error: &'static str,
+        reason: String,
+    },
+    /// The transaction was applied, but could not be recorded (i.e., onto the
+    /// journal).
+    Failed {
+        error: &'static str,
+        reason: String,
+    },
+    Balance(RawClient),
+}
+
+impl Response {
+    fn rejected(error: EngineError) -> Self {
+        Self::Rejected {
+            error: error.class(),
+            reason: error.to_string(),
+        }
+    }
+
+    fn failed(error: EngineError) -> Self {
+        Self::Failed {
+            error: error.class(),
+            reason: error.to_string(),
+        }
+    }
+}
+
+/// Accepts connections on `listener` forever, serving each of them onto
+/// `core` from its own thread.
+///
+/// Only returns if accepting a connection fails; a failing connection is
+/// merely dropped.
+pub(crate) fn serve(core: Core, listener: TcpListener) -> EngineResult<()> {
+    let core = Mutex::new(core);
+    thread::scope(|scope| loop {
+        let (stream, _) = listener.accept()?;
+        let core = &core;
+        scope.spawn(move || handle(core, stream));
+    })
+}
+
+/// Answers every request read from `stream`, until it is closed.
+fn handle(core: &Mutex<Core>, stream: TcpStream) -> io::Result<()> {
+    let mut reader = io::BufReader::new(stream.try_clone()?);
+    let mut writer = io::BufWriter::new(stream);
+    let mut raw_line = vec![];
+    let mut line = 0;
+    loop {
+        raw_line.clear();
+        let read = (&mut reader)
+            .take(MAX_LINE as u64)
+            .read_until(b'\n', &mut raw_line)?;
+        if read == 0 {
+            return Ok(());
+        };
+        line += 1;
+        let complete = raw_line.len() < MAX_LINE || raw_line.ends_with(b"\n");
+        let text = raw_line.trim_ascii();
+        if complete && text.is_empty() {
+            continue;
+        };
+        let request = match complete {
+            true => parse_request(text, line),
+            false => {
+                skip_line(&mut reader)?;
+                Err(EngineError::ParseError {
+                    line,
+                    column: None,
+                    kind: ParseErrorKind::Malformed(format!(
+                        "the request is longer than {} bytes",
+                        MAX_LINE
+                    )),
+                })
+            },
+        };
+        let response = match request {
+            Ok(request) => {
+                let mut core =
+                    core.lock().unwrap_or_else(PoisonError::into_inner);
+                respond(&mut core, request)
+            },
+            Err(error) => Response::rejected(error),
+        };
+        serde_json::to_writer(&mut writer, &response)?;
+        writeln!(writer)?;
+        writer.flush()?;
+    }
+}
+
+/// Discards the rest of the current line of `reader`, without keeping it in
+/// memory.
+fn skip_line<R>(reader: &mut R) -> io::Result<()>
+where
+    R: BufRead,
+{
+    loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            return Ok(());
+        };
+        match buffer.iter().position(|byte| *byte == b'\n') {
+            Some(end) => {
+                reader.consume(end + 1);
+                return Ok(());
+            },
+            None => {
+                let len = buffer.len();
+                reader.consume(len);
+            },
+        };
+    }
+}
+
+/// Parses a single request, as JSON if it starts with `{`, and as CSV
+/// otherwise.
+fn parse_request(text: &[u8], line: u64) -> EngineResult<Request> {
+    let format = match text.starts_with(b"{") {
+        true => InputFormat::Jsonl,
+        false => InputFormat::Csv,
+    };
+    let query = match format {
+        InputFormat::Csv => parse_csv_query(text),
+        InputFormat::Jsonl => serde_json::from_slice::<RawQuery>(text)
+            .ok()
+            .filter(|raw_query| raw_query.r#type == BALANCE)
+            .map(|raw_query| Some(raw_query.client)),
+    };
+    match query {
+        Some(Some(client)) => Ok(Request::Balance(client)),
+        Some(None) => Err(EngineError::ParseError {
+            line,
+            column: Some(2),
+            kind: ParseErrorKind::Malformed(
+                "a balance query requires a single client id".to_owned(),
+            ),
+        }),
+        None => deserializer::parse_record(text, line, format)
+            .map(Request::Transaction),
+    }
+}
+
+/// Parses a CSV balance query: `None` if `text` is not a balance query at all,
+/// or `Some(None)` if it does not hold a single valid client id (trailing empty
+/// fields being allowed, like for disputes).
+fn parse_csv_query(text: &[u8]) -> Option<Option<ClientId>> {
+    let mut fields = text.split(|byte| *byte == b',').map(<[u8]>::trim_ascii);
+    if fields.next() != Some(BALANCE.as_bytes()) {
+        return None;
+    };
+    let client = fields
+        .next()
+        .and_then(|client| std::str::from_utf8(client).ok())
+        .and_then(|client| client.parse().ok());
+    match fields.all(<[u8]>::is_empty) {
+        true => Some(client),
+        false => Some(None),
+    }
+}
+
+fn respond(core: &mut Core, request: Request) -> Response {
+    match request {
+        Request::Transaction(transaction) => {
+            match core.process_and_record(transaction) {
+                Ok(Ok(())) => Response::Accepted,
+                Ok(Err(error)) => Response::failed(error),
+                Err(error) => Response::rejected(error),
+            }
+        },
+        Request::Balance(client) => match core.client(client) {
+            Some(client) => Response::Balance(client.into()),
+            None => Response::rejected(EngineError::UnknownClient { client }),
+        },
+    }
+}
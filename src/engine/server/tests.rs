@@ -0,0 +1,96 @@
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::thread;
+
+use super::MAX_LINE;
+use crate::engine::Engine;
+
+/// Serves a fresh engine on an ephemeral port of localhost, returning a
+/// connection to it.
+fn connect() -> (TcpStream, SocketAddr) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    thread::spawn(move || Engine::default().serve(listener));
+    (TcpStream::connect(address).unwrap(), address)
+}
+
+/// Sends each of `requests` over `stream`, returning the responses.
+fn exchange(stream: &TcpStream, requests: &[&str]) -> Vec<String> {
+    let mut reader = BufReader::new(stream);
+    let mut writer = stream;
+    requests
+        .iter()
+        .map(|request| {
+            writeln!(writer, "{}", request).unwrap();
+            let mut response = String::new();
+            reader.read_line(&mut response).unwrap();
+            response.trim_end().to_owned()
+        })
+        .collect()
+}
+
+#[test]
+fn serve() {
+    let (stream, _) = connect();
+    let responses = exchange(&stream, &[
+        "deposit, 1, 1, 2.5",
+        r#"{"type": "withdrawal", "client": 1, "tx": 2, "amount": 5}"#,
+        r#"{"type": "dispute", "client": 1, "tx": 1}"#,
+        "balance, 1",
+        r#"{"type": "balance", "client": 1}"#,
+    ]);
+    assert_eq!(responses, vec![
+        r#"{"status":"accepted"}"#,
+        r#"{"status":"rejected","error":"insufficient_funds","reason":"client 1 does not have enough available funds"}"#,
+        r#"{"status":"accepted"}"#,
+        r#"{"status":"balance","client":1,"available":"0.0000","held":"2.5000","total":"2.5000","locked":false,"account_status":"active"}"#,
+        r#"{"status":"balance","client":1,"available":"0.0000","held":"2.5000","total":"2.5000","locked":false,"account_status":"active"}"#,
+    ]);
+}
+
+#[test]
+fn serve_invalid_requests() {
+    let (stream, _) = connect();
+    let responses = exchange(&stream, &[
+        "deposit, 1, 1, -2",
+        "{oops",
+        "balance, 1",
+        "balance, one",
+    ]);
+    assert_eq!(responses, vec![
+        r#"{"status":"rejected","error":"negative_amount","reason":"line 1, column 4: amount '-2' is negative"}"#,
+        r#"{"status":"rejected","error":"malformed","reason":"line 2, column 2: malformed record (key must be a string)"}"#,
+        r#"{"status":"rejected","error":"unknown_client","reason":"client 1 does not exist"}"#,
+        r#"{"status":"rejected","error":"malformed","reason":"line 4, column 2: malformed record (a balance query requires a single client id)"}"#,
+    ]);
+}
+
+#[test]
+fn serve_long_lines() {
+    let (stream, _) = connect();
+    let long = format!("deposit, 1, 1, 1{}", " ".repeat(MAX_LINE));
+    let responses = exchange(&stream, &[&long, "deposit, 1, 1, 2"]);
+    assert_eq!(responses, vec![
+        r#"{"status":"rejected","error":"malformed","reason":"line 1: malformed record (the request is longer than 65536 bytes)"}"#,
+        r#"{"status":"accepted"}"#,
+    ]);
+}
+
+#[test]
+fn serve_shared_core() {
+    let (stream, address) = connect();
+    exchange(&stream, &["deposit, 1, 1, 2"]);
+    drop(stream);
+    let other_stream = TcpStream::connect(address).unwrap();
+    assert_eq!(
+        exchange(&other_stream, &["deposit, 1, 1, 2", "balance, 1,"]),
+        vec![
+            r#"{"status":"rejected","error":"duplicate_tx","reason":"transaction 1 already exists"}"#,
+            r#"{"status":"balance","client":1,"available":"2.0000","held":"0.0000","total":"2.0000","locked":false,"account_status":"active"}"#,
+        ]
+    );
+}
@@ -0,0 +1,127 @@
+//! Statements of a single client: every transaction which referred to it (as
+//! its own client, or as the destination of a transfer), in order, along with
+//! the client's balances after each of them.
+
+#[cfg(test)]
+mod tests;
+
+use std::io;
+
+use crate::client::Status;
+use crate::engine::core::Core;
+use crate::engine::deserializer;
+use crate::engine::deserializer::InputFormat;
+use crate::error::EngineError;
+use crate::error::Reject;
+use crate::transaction::Charge;
+use crate::transaction::ChargeKind;
+use crate::transaction::Transaction;
+use crate::types::Amount;
+use crate::types::ClientId;
+use crate::types::EngineResult;
+
+/// A single step of a statement.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StatementEntry {
+    pub line: u64,
+    pub transaction: Transaction,
+    /// The amount which the transaction carried or, failing that, the amount
+    /// of the charge which it referred to (if that charge exists).
+    pub amount: Option<Amount>,
+    pub available: Amount,
+    pub held: Amount,
+    pub total: Amount,
+    pub status: Status,
+    /// Why the transaction was refused, if it was.
+    pub refusal: Option<EngineError>,
+}
+
+/// Processes every record read from `src` (in `format`) onto `core`, returning
+/// the statement of `client`.
+///
+/// Every refused record is handed to `reject`, whether or not it refers to
+/// `client`.
+pub(crate) fn process<S, R>(
+    core: &mut Core,
+    src: S,
+    format: InputFormat,
+    client: ClientId,
+    reject: R,
+) -> EngineResult<Vec<StatementEntry>>
+where
+    S: io::Read,
+    R: FnMut(Reject) -> EngineResult<()>,
+{
+    let mut entries = vec![];
+    deserializer::deserialize_reader_with_origins(
+        src,
+        format,
+        |transaction, origin| {
+            if !involves(core, &transaction, client)? {
+                return core.process(transaction);
+            };
+            let amount = match transaction.amount() {
+                Some(amount) => Some(amount),
+                None => referenced_charge(core, &transaction)?
+                    .map(|(_, Charge { amount, .. })| amount),
+            };
+            let result = core.process(transaction.clone());
+            let (available, held, total, status) = match core.client(client) {
+                Some(client) => (
+                    client.available(),
+                    client.held(),
+                    client.total(),
+                    client.status(),
+                ),
+                None => {
+                    (Amount::ZERO, Amount::ZERO, Amount::ZERO, Status::Active)
+                },
+            };
+            entries.push(StatementEntry {
+                line: origin.line,
+                transaction,
+                amount,
+                available,
+                held,
+                total,
+                status,
+                refusal: result.clone().err(),
+            });
+            result
+        },
+        reject,
+    )?;
+    Ok(entries)
+}
+
+/// Whether `transaction` refers to `client`, either as its own client or as the
+/// destination of the transfer which it is (or which it refers to).
+fn involves(
+    core: &Core,
+    transaction: &Transaction,
+    client: ClientId,
+) -> EngineResult<bool> {
+    if transaction.client() == client {
+        return Ok(true);
+    };
+    let kind = match transaction.as_charge() {
+        Some((kind, _)) => Some(kind),
+        None => referenced_charge(core, transaction)?.map(|(kind, _)| kind),
+    };
+    Ok(kind == Some(ChargeKind::Transfer { to: client }))
+}
+
+/// The stored charge which `transaction` refers to, if it is a dispute, a
+/// resolve or a chargeback (and if that charge exists).
+fn referenced_charge(
+    core: &Core,
+    transaction: &Transaction,
+) -> EngineResult<Option<(ChargeKind, Charge)>> {
+    let Some(tx) = transaction.referenced_tx() else {
+        return Ok(None);
+    };
+    Ok(core.transaction(tx)?.and_then(|transaction_wrapper| {
+        let (kind, charge) = transaction_wrapper.transaction().as_charge()?;
+        Some((kind, charge.clone()))
+    }))
+}
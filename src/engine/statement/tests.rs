@@ -0,0 +1,139 @@
+use super::StatementEntry;
+use crate::engine::Engine;
+use crate::error::EngineError;
+use crate::types::Amount;
+
+const SRC: &str = "type, client, tx, amount\n\
+                   deposit, 1, 1, 2\n\
+                   deposit, 2, 2, 5\n\
+                   withdrawal, 1, 3, 5\n\
+                   dispute, 1, 1,\n\
+                   dispute, 1, 2,\n\
+                   resolve, 1, 1,\n\
+                   withdrawal, 1, 4, 0.5\n";
+
+fn entry(
+    line: u64,
+    transaction: crate::Transaction,
+    amount: Option<Amount>,
+    balances: [Amount; 3],
+    refusal: Option<EngineError>,
+) -> StatementEntry {
+    let [available, held, total] = balances;
+    StatementEntry {
+        line,
+        transaction,
+        amount,
+        available,
+        held,
+        total,
+        status: crate::client::Status::Active,
+        refusal,
+    }
+}
+
+#[test]
+fn statement() {
+    let mut engine = Engine::default();
+    let mut rejects = vec![];
+    let entries = engine
+        .statement(SRC.as_bytes(), 1, |reject| {
+            rejects.push(reject.line);
+            Ok(())
+        })
+        .unwrap();
+
+    assert_eq!(entries, vec![
+        entry(
+            2,
+            transaction!(["deposit", 1, 1, 2]),
+            Some(amount!(2)),
+            [amount!(2), amount!(0), amount!(2)],
+            None,
+        ),
+        entry(
+            4,
+            transaction!(["withdrawal", 1, 3, 5]),
+            Some(amount!(5)),
+            [amount!(2), amount!(0), amount!(2)],
+            Some(EngineError::InsufficientFunds { client: 1 }),
+        ),
+        entry(
+            5,
+            transaction!(["dispute", 1, 1]),
+            Some(amount!(2)),
+            [amount!(0), amount!(2), amount!(2)],
+            None,
+        ),
+        entry(
+            6,
+            transaction!(["dispute", 1, 2]),
+            Some(amount!(5)),
+            [amount!(0), amount!(2), amount!(2)],
+            Some(EngineError::ClientMismatch {
+                client: 1,
+                tx: 2,
+                owner: 2,
+            }),
+        ),
+        entry(
+            7,
+            transaction!(["resolve", 1, 1]),
+            Some(amount!(2)),
+            [amount!(2), amount!(0), amount!(2)],
+            None,
+        ),
+        entry(
+            8,
+            transaction!(["withdrawal", 1, 4, 0.5]),
+            Some(amount!(0.5)),
+            [amount!(1.5), amount!(0), amount!(1.5)],
+            None,
+        ),
+    ]);
+    assert_eq!(rejects, vec![4, 6]);
+}
+
+#[test]
+fn statement_of_unknown_client() {
+    let mut engine = Engine::default();
+    let entries = engine.statement(SRC.as_bytes(), 3, |_| Ok(())).unwrap();
+    assert_eq!(entries, vec![]);
+    let mut dst = vec![];
+    engine.write(&mut dst).unwrap();
+
+    // The rest of the input is processed all the same.
+    assert_eq!(
+        String::from_utf8(dst).unwrap(),
+        "client,available,held,total,locked,account_status\n\
+         1,1.5000,0.0000,1.5000,false,active\n\
+         2,5.0000,0.0000,5.0000,false,active\n"
+    );
+}
+
+#[test]
+fn statement_of_transfer_destination() {
+    let src = "type, client, tx, amount, to\n\
+               deposit, 1, 1, 2,\n\
+               transfer, 1, 2, 0.5, 2\n\
+               dispute, 1, 2,,\n";
+    let mut engine = Engine::default();
+    let entries = engine.statement(src.as_bytes(), 2, |_| Ok(())).unwrap();
+
+    assert_eq!(entries, vec![
+        entry(
+            3,
+            transaction!(["transfer", 1, 2, 0.5, 2]),
+            Some(amount!(0.5)),
+            [amount!(0.5), amount!(0), amount!(0.5)],
+            None,
+        ),
+        entry(
+            4,
+            transaction!(["dispute", 1, 2]),
+            Some(amount!(0.5)),
+            [amount!(0), amount!(0.5), amount!(0.5)],
+            None,
+        ),
+    ]);
+}
@@ -0,0 +1,33 @@
+use crate::error::EngineError;
+
+#[test]
+fn process_reader_and_write() {
+    let src = "type, client, tx, amount\n\
+               deposit, 1, 1, 2\n\
+               withdrawal, 1, 2, 5\n\
+               deposit, 2, 3, 0.5\n";
+    let mut engine = super::Engine::default();
+    let mut rejects = vec![];
+    engine
+        .process_reader(src.as_bytes(), |reject| {
+            rejects.push(reject);
+            Ok(())
+        })
+        .unwrap();
+    let src = "type, client, tx, amount\n\
+               dispute, 1, 1,\n";
+    engine.process_reader(src.as_bytes(), |_| Ok(())).unwrap();
+    let mut dst = vec![];
+    engine.write(&mut dst).unwrap();
+
+    assert_eq!(rejects.len(), 1);
+    assert_eq!(rejects[0].reason, EngineError::InsufficientFunds {
+        client: 1
+    });
+    assert_eq!(
+        String::from_utf8(dst).unwrap(),
+        "client,available,held,total,locked,account_status\n\
+         1,0.0000,2.0000,2.0000,false,active\n\
+         2,0.5000,0.0000,0.5000,false,active\n"
+    );
+}
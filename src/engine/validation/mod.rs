@@ -0,0 +1,49 @@
+//! Validation of input files, which reports every problem they contain
+//! instead of the accounts they result in.
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::error::Reject;
+
+/// A tally of the refused records of an input, by class of error.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Summary {
+    classes: BTreeMap<&'static str, u64>,
+}
+
+impl Summary {
+    pub fn add(&mut self, reject: &Reject) {
+        *self.classes.entry(reject.reason.class()).or_default() += 1;
+    }
+
+    /// The number of refused records, by class of error.
+    pub fn classes(&self) -> &BTreeMap<&'static str, u64> {
+        &self.classes
+    }
+
+    pub fn problems(&self) -> u64 {
+        self.classes.values().sum()
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.classes.is_empty()
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.problems() {
+            0 => return writeln!(f, "No problems found."),
+            1 => writeln!(f, "1 problem found:")?,
+            problems => writeln!(f, "{} problems found:", problems)?,
+        };
+        for (class, count) in &self.classes {
+            writeln!(f, "    {}: {}", class, count)?;
+        }
+        Ok(())
+    }
+}
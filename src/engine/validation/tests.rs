@@ -0,0 +1,62 @@
+use super::Summary;
+use crate::engine::Engine;
+
+#[test]
+fn summary() {
+    let src = "type, client, tx, amount\n\
+               deposit, 1, 1, 2\n\
+               deposit, 1, 1, 2\n\
+               deposit, 2, 1, 2\n\
+               withdrawal, 1, 2, 5\n\
+               deposit, 1, 3, 0.00001\n\
+               dispute, 1, 9,\n\
+               refund, 1, 4, 1\n\
+               deposit, 1, 5, -1\n\
+               dispute, 1, 1, 3\n\
+               deposit, 1, 6, abc\n";
+    let mut engine = Engine::default();
+    let mut summary = Summary::default();
+    let mut problems = vec![];
+    engine
+        .process_reader(src.as_bytes(), |reject| {
+            summary.add(&reject);
+            problems.push(reject.to_string());
+            Ok(())
+        })
+        .unwrap();
+
+    assert_eq!(summary.problems(), 9);
+    assert_eq!(summary.classes().iter().collect::<Vec<_>>(), vec![
+        (&"duplicate_tx", &2),
+        (&"excess_precision", &1),
+        (&"excessive_dispute", &1),
+        (&"insufficient_funds", &1),
+        (&"invalid_amount", &1),
+        (&"negative_amount", &1),
+        (&"unknown_tx", &1),
+        (&"unknown_type", &1),
+    ]);
+    assert_eq!(
+        problems[0],
+        "line 3: transaction 1 already exists (`deposit, 1, 1, 2`)"
+    );
+    assert_eq!(
+        summary.to_string(),
+        "9 problems found:\n\
+         \x20   duplicate_tx: 2\n\
+         \x20   excess_precision: 1\n\
+         \x20   excessive_dispute: 1\n\
+         \x20   insufficient_funds: 1\n\
+         \x20   invalid_amount: 1\n\
+         \x20   negative_amount: 1\n\
+         \x20   unknown_tx: 1\n\
+         \x20   unknown_type: 1\n"
+    );
+}
+
+#[test]
+fn clean_summary() {
+    let summary = Summary::default();
+    assert!(summary.is_clean());
+    assert_eq!(summary.to_string(), "No problems found.\n");
+}
@@ -0,0 +1,351 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use crate::transaction::DisputeState;
+use crate::types::ClientId;
+use crate::types::TransactionId;
+
+/// Every way in which the engine can refuse an input.
+///
+/// Each variant carries the context necessary to locate its cause (i.e., the
+/// client, the transaction, or the line of the input source).
+#[derive(Clone, Debug, PartialEq)]
+pub enum EngineError {
+    InsufficientFunds {
+        client: ClientId,
+    },
+    InsufficientHeldFunds {
+        client: ClientId,
+    },
+    AccountLocked {
+        client: ClientId,
+    },
+    AccountClosed {
+        client: ClientId,
+    },
+    AccountNotFrozen {
+        client: ClientId,
+    },
+    /// An account which still holds funds cannot be closed.
+    AccountNotEmpty {
+        client: ClientId,
+    },
+    BalanceOverflow {
+        client: ClientId,
+    },
+    DuplicateTx {
+        tx: TransactionId,
+    },
+    UnknownTx {
+        tx: TransactionId,
+    },
+    UnknownClient {
+        client: ClientId,
+    },
+    SelfTransfer {
+        client: ClientId,
+    },
+    NotDisputable {
+        tx: TransactionId,
+    },
+    ClientMismatch {
+        client: ClientId,
+        tx: TransactionId,
+        owner: ClientId,
+    },
+    /// A dispute is for more than the amount of its charge.
+    ExcessiveDispute {
+        tx: TransactionId,
+    },
+    /// A resolve or chargeback is for more than the disputed amount.
+    ExcessiveSettlement {
+        tx: TransactionId,
+    },
+    IllegalTransition {
+        tx: TransactionId,
+        from: DisputeState,
+        to: DisputeState,
+    },
+    ParseError {
+        line: u64,
+        column: Option<u64>,
+        kind: ParseErrorKind,
+    },
+    Io {
+        reason: String,
+    },
+    InvalidSnapshot {
+        line: u64,
+        reason: String,
+    },
+    InvalidJournal {
+        line: u64,
+        reason: String,
+    },
+    ShardUnavailable {
+        shard: usize,
+    },
+    /// A record was refused while processing in strict mode, which aborts on
+    /// the first refusal.
+    Refused(Box<Reject>),
+}
+
+/// An input record which was refused, either because it could not be parsed or
+/// because the engine could not apply it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reject {
+    pub line: u64,
+    pub record: String,
+    pub reason: EngineError,
+}
+
+/// The reason for which a single input record could not be parsed.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParseErrorKind {
+    UnknownType(String),
+    MissingAmount,
+    /// The amount is not a decimal.
+    InvalidAmount(String),
+    /// The amount is a decimal, but with more than four fractional digits.
+    ExcessPrecision(String),
+    NonFiniteAmount(String),
+    NegativeAmount(String),
+    ZeroAmount,
+    /// A freeze, unfreeze or close carries an amount.
+    UnexpectedAmount(String),
+    /// A transfer does not name the client which it moves funds into.
+    MissingDestination,
+    /// A transaction other than a transfer names a destination client.
+    UnexpectedDestination(ClientId),
+    /// A freeze, unfreeze or close does not carry a reason code.
+    MissingReason,
+    /// The reason code is not made of ASCII letters, digits, `_` and `-`.
+    InvalidReason(String),
+    /// A transaction other than an administrative action carries a reason.
+    UnexpectedReason(String),
+    Malformed(String),
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InsufficientFunds { client } => write!(
+                f,
+                "client {} does not have enough available funds",
+                client
+            ),
+            Self::InsufficientHeldFunds { client } => {
+                write!(f, "client {} does not have enough held funds", client)
+            },
+            Self::AccountLocked { client } => {
+                write!(f, "client {} is locked", client)
+            },
+            Self::AccountClosed { client } => {
+                write!(f, "client {} is closed", client)
+            },
+            Self::AccountNotFrozen { client } => {
+                write!(f, "client {} is not frozen", client)
+            },
+            Self::AccountNotEmpty { client } => {
+                write!(f, "client {} still holds funds", client)
+            },
+            Self::BalanceOverflow { client } => {
+                write!(f, "the balance of client {} would overflow", client)
+            },
+            Self::DuplicateTx { tx } => {
+                write!(f, "transaction {} already exists", tx)
+            },
+            Self::UnknownTx { tx } => {
+                write!(f, "transaction {} does not exist", tx)
+            },
+            Self::UnknownClient { client } => {
+                write!(f, "client {} does not exist", client)
+            },
+            Self::SelfTransfer { client } => {
+                write!(f, "client {} cannot transfer funds to itself", client)
+            },
+            Self::NotDisputable { tx } => {
+                write!(f, "transaction {} is not disputable", tx)
+            },
+            Self::ClientMismatch { client, tx, owner } => write!(
+                f,
+                "client {} cannot refer to transaction {}, \
+                 which belongs to client {}",
+                client, tx, owner
+            ),
+            Self::ExcessiveDispute { tx } => write!(
+                f,
+                "cannot dispute more than the amount of transaction {}",
+                tx
+            ),
+            Self::ExcessiveSettlement { tx } => write!(
+                f,
+                "cannot settle more than the disputed amount of transaction {}",
+                tx
+            ),
+            Self::IllegalTransition { tx, from, to } => write!(
+                f,
+                "transaction {} cannot go from being {} to being {}",
+                tx, from, to
+            ),
+            Self::ParseError {
+                line,
+                column: Some(column),
+                kind,
+            } => write!(f, "line {}, column {}: {}", line, column, kind),
+            Self::ParseError {
+                line,
+                column: None,
+                kind,
+            } => write!(f, "line {}: {}", line, kind),
+            Self::Io { reason } => write!(f, "i/o error: {}", reason),
+            Self::InvalidSnapshot { line, reason } => {
+                write!(f, "line {} of the snapshot: {}", line, reason)
+            },
+            Self::InvalidJournal { line, reason } => {
+                write!(f, "line {} of the journal: {}", line, reason)
+            },
+            Self::ShardUnavailable { shard } => {
+                write!(f, "worker {} stopped unexpectedly", shard)
+            },
+            Self::Refused(reject) => write!(f, "{}", reject),
+        }
+    }
+}
+
+impl fmt::Display for Reject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.reason {
+            EngineError::ParseError { .. } => {
+                write!(f, "{} (`{}`)", self.reason, self.record)
+            },
+            reason => {
+                write!(f, "line {}: {} (`{}`)", self.line, reason, self.record)
+            },
+        }
+    }
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownType(r#type) => {
+                write!(f, "unknown transaction type '{}'", r#type)
+            },
+            Self::MissingAmount => write!(f, "missing amount"),
+            Self::InvalidAmount(amount) => {
+                write!(f, "amount '{}' is not a decimal", amount)
+            },
+            Self::ExcessPrecision(amount) => write!(
+                f,
+                "amount '{}' has more than four fractional digits",
+                amount
+            ),
+            Self::NonFiniteAmount(amount) => {
+                write!(f, "amount '{}' is not finite", amount)
+            },
+            Self::NegativeAmount(amount) => {
+                write!(f, "amount '{}' is negative", amount)
+            },
+            Self::ZeroAmount => write!(f, "amount is zero"),
+            Self::UnexpectedAmount(amount) => write!(
+                f,
+                "unexpected amount '{}' \
+                 (freezes, unfreezes and closes do not carry one)",
+                amount
+            ),
+            Self::MissingDestination => write!(f, "missing destination client"),
+            Self::UnexpectedDestination(to) => write!(
+                f,
+                "unexpected destination client {} \
+                 (only transfers carry one)",
+                to
+            ),
+            Self::MissingReason => write!(f, "missing reason code"),
+            Self::InvalidReason(reason) => write!(
+                f,
+                "reason code '{}' \
+                 is not made of letters, digits, '_' and '-'",
+                reason
+            ),
+            Self::UnexpectedReason(reason) => write!(
+                f,
+                "unexpected reason code '{}' \
+                 (only freezes, unfreezes and closes carry one)",
+                reason
+            ),
+            Self::Malformed(reason) => {
+                write!(f, "malformed record ({})", reason)
+            },
+        }
+    }
+}
+
+impl EngineError {
+    /// The class of `self`, which groups errors of the same kind regardless of
+    /// their context.
+    pub fn class(&self) -> &'static str {
+        match self {
+            Self::InsufficientFunds { .. } => "insufficient_funds",
+            Self::InsufficientHeldFunds { .. } => "insufficient_held_funds",
+            Self::AccountLocked { .. } => "account_locked",
+            Self::AccountClosed { .. } => "account_closed",
+            Self::AccountNotFrozen { .. } => "account_not_frozen",
+            Self::AccountNotEmpty { .. } => "account_not_empty",
+            Self::BalanceOverflow { .. } => "balance_overflow",
+            Self::DuplicateTx { .. } => "duplicate_tx",
+            Self::UnknownTx { .. } => "unknown_tx",
+            Self::UnknownClient { .. } => "unknown_client",
+            Self::SelfTransfer { .. } => "self_transfer",
+            Self::NotDisputable { .. } => "not_disputable",
+            Self::ClientMismatch { .. } => "client_mismatch",
+            Self::ExcessiveDispute { .. } => "excessive_dispute",
+            Self::ExcessiveSettlement { .. } => "excessive_settlement",
+            Self::IllegalTransition { .. } => "illegal_transition",
+            Self::ParseError { kind, .. } => kind.class(),
+            Self::Io { .. } => "io",
+            Self::InvalidSnapshot { .. } => "invalid_snapshot",
+            Self::InvalidJournal { .. } => "invalid_journal",
+            Self::ShardUnavailable { .. } => "shard_unavailable",
+            Self::Refused(reject) => reject.reason.class(),
+        }
+    }
+}
+
+impl ParseErrorKind {
+    pub fn class(&self) -> &'static str {
+        match self {
+            Self::UnknownType(_) => "unknown_type",
+            Self::MissingAmount => "missing_amount",
+            Self::InvalidAmount(_) => "invalid_amount",
+            Self::ExcessPrecision(_) => "excess_precision",
+            Self::NonFiniteAmount(_) => "non_finite_amount",
+            Self::NegativeAmount(_) => "negative_amount",
+            Self::ZeroAmount => "zero_amount",
+            Self::UnexpectedAmount(_) => "unexpected_amount",
+            Self::MissingDestination => "missing_destination",
+            Self::UnexpectedDestination(_) => "unexpected_destination",
+            Self::MissingReason => "missing_reason",
+            Self::InvalidReason(_) => "invalid_reason",
+            Self::UnexpectedReason(_) => "unexpected_reason",
+            Self::Malformed(_) => "malformed",
+        }
+    }
+}
+
+impl Error for EngineError {}
+
+impl From<Reject> for EngineError {
+    fn from(reject: Reject) -> Self {
+        Self::Refused(Box::new(reject))
+    }
+}
+
+impl From<io::Error> for EngineError {
+    fn from(error: io::Error) -> Self {
+        Self::Io {
+            reason: error.to_string(),
+        }
+    }
+}
@@ -0,0 +1,176 @@
+//! A streaming payments engine.
+//!
+//! Transactions are read by the [`engine::deserializer`], applied onto client
+//! accounts by the [`Core`], and the resulting accounts are written out by the
+//! [`engine::serializer`]. The [`Engine`] ties these three phases together.
+
+#[cfg(test)]
+macro_rules! assert_clients_eq {
+    ($core:ident == [$($client:expr),*$(,)?]) => {{
+        let crate::engine::core::Core { clients, .. } = $core;
+        let actual_clients = clients.into_values().collect::<Vec<_>>();
+        let expected_clients: Vec<crate::client::Client> = vec![$($client),*];
+        assert_eq!(actual_clients, expected_clients);
+    }};
+}
+
+#[cfg(test)]
+macro_rules! amount {
+    ($amount:literal) => {
+        stringify!($amount).parse::<crate::types::Amount>().unwrap()
+    };
+}
+
+#[cfg(test)]
+macro_rules! client {
+    ([$client:expr, $available:literal, $held:literal, $locked:expr]) => {
+        crate::client::Client::with_balances(
+            $client,
+            amount!($available),
+            amount!($held),
+            crate::client::Status::from($locked),
+        )
+    };
+}
+
+#[cfg(test)]
+macro_rules! transaction {
+    (["deposit", $client:expr, $tx:expr, $amount:literal]) => {
+        crate::transaction::Transaction::Deposit(crate::transaction::Charge {
+            client: $client,
+            tx: $tx,
+            amount: amount!($amount),
+        })
+    };
+    (["withdrawal", $client:expr, $tx:expr, $amount:literal]) => {
+        crate::transaction::Transaction::Withdrawal(
+            crate::transaction::Charge {
+                client: $client,
+                tx: $tx,
+                amount: amount!($amount),
+            },
+        )
+    };
+    (["transfer", $client:expr, $tx:expr, $amount:literal, $to:expr]) => {
+        crate::transaction::Transaction::Transfer(
+            crate::transaction::Transfer {
+                charge: crate::transaction::Charge {
+                    client: $client,
+                    tx: $tx,
+                    amount: amount!($amount),
+                },
+                to: $to,
+            },
+        )
+    };
+    (["dispute", $client:expr, $tx:expr]) => {
+        crate::transaction::Transaction::Dispute(
+            crate::transaction::ChargeRef {
+                client: $client,
+                tx: $tx,
+                amount: None,
+            },
+        )
+    };
+    (["dispute", $client:expr, $tx:expr, $amount:literal]) => {
+        crate::transaction::Transaction::Dispute(
+            crate::transaction::ChargeRef {
+                client: $client,
+                tx: $tx,
+                amount: Some(amount!($amount)),
+            },
+        )
+    };
+    (["resolve", $client:expr, $tx:expr]) => {
+        crate::transaction::Transaction::Resolve(
+            crate::transaction::ChargeRef {
+                client: $client,
+                tx: $tx,
+                amount: None,
+            },
+        )
+    };
+    (["resolve", $client:expr, $tx:expr, $amount:literal]) => {
+        crate::transaction::Transaction::Resolve(
+            crate::transaction::ChargeRef {
+                client: $client,
+                tx: $tx,
+                amount: Some(amount!($amount)),
+            },
+        )
+    };
+    (["chargeback", $client:expr, $tx:expr]) => {
+        crate::transaction::Transaction::Chargeback(
+            crate::transaction::ChargeRef {
+                client: $client,
+                tx: $tx,
+                amount: None,
+            },
+        )
+    };
+    (["chargeback", $client:expr, $tx:expr, $amount:literal]) => {
+        crate::transaction::Transaction::Chargeback(
+            crate::transaction::ChargeRef {
+                client: $client,
+                tx: $tx,
+                amount: Some(amount!($amount)),
+            },
+        )
+    };
+    (["freeze", $client:expr, $tx:expr, $reason:literal]) => {
+        crate::transaction::Transaction::Freeze(
+            crate::transaction::AccountAction {
+                client: $client,
+                tx: $tx,
+                reason: $reason.to_owned(),
+            },
+        )
+    };
+    (["unfreeze", $client:expr, $tx:expr, $reason:literal]) => {
+        crate::transaction::Transaction::Unfreeze(
+            crate::transaction::AccountAction {
+                client: $client,
+                tx: $tx,
+                reason: $reason.to_owned(),
+            },
+        )
+    };
+    (["close", $client:expr, $tx:expr, $reason:literal]) => {
+        crate::transaction::Transaction::Close(
+            crate::transaction::AccountAction {
+                client: $client,
+                tx: $tx,
+                reason: $reason.to_owned(),
+            },
+        )
+    };
+}
+
+#[cfg(test)]
+macro_rules! process {
+    ([$($transaction:expr),*$(,)?] -> $core:expr) => {
+        $($core.process($transaction).unwrap();)*
+    };
+}
+
+pub mod amount;
+pub mod client;
+pub mod engine;
+pub mod error;
+pub mod transaction;
+
+pub mod types {
+    pub use crate::amount::Amount;
+
+    pub type EngineResult<T> = Result<T, crate::error::EngineError>;
+    pub type ClientId = u16;
+    pub type TransactionId = u32;
+}
+
+pub use crate::client::Client;
+pub use crate::engine::core::Core;
+pub use crate::engine::core::Policy;
+pub use crate::engine::Engine;
+pub use crate::error::EngineError;
+pub use crate::error::Reject;
+pub use crate::transaction::Transaction;
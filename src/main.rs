@@ -1,84 +1,330 @@
 use std::env;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::net::TcpListener;
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::path::PathBuf;
 use std::process::ExitCode;
 
-#[cfg(test)]
-macro_rules! assert_clients_eq {
-    ($core:ident == [$($client:expr)*]) => {{
-        let crate::engine::core::Core { clients, .. } = $core;
-        let actual_clients = clients.into_values().collect::<Vec<_>>();
-        #[allow(unused_mut)]
-        let mut expected_clients = Vec::<crate::client::Client>::new();
-        $(expected_clients.push($client);)*
-        assert_eq!(actual_clients, expected_clients);
-    }};
+use rust_coding_test::engine::core::store::FileStore;
+use rust_coding_test::engine::deserializer;
+use rust_coding_test::engine::deserializer::InputFormat;
+use rust_coding_test::engine::journal::Journal;
+use rust_coding_test::engine::serializer;
+use rust_coding_test::engine::serializer::Format;
+use rust_coding_test::engine::validation::Summary;
+use rust_coding_test::types::ClientId;
+use rust_coding_test::types::EngineResult;
+use rust_coding_test::Core;
+use rust_coding_test::Engine;
+use rust_coding_test::Policy;
+use rust_coding_test::Reject;
+
+const USAGE: &str = "\
+Oops, this binary requires the relative path to an input file (or `-` to read
+from stdin).
+
+Usage:
+    rust_coding_test <transactions> [options]
+    rust_coding_test replay <journal> [options]
+    rust_coding_test statement --client <id> <transactions> [options]
+    rust_coding_test validate <transactions> [options]
+    rust_coding_test serve <address> [options]
+
+Options:
+    --input-format <fmt>     Reads the input as `csv` or `jsonl` (by default,
+                             `jsonl` for `.jsonl` and `.ndjson` files, and
+                             `csv` otherwise).
+    --output <path>          Writes the output into a file instead of stdout.
+    --output-format <fmt>    Writes the output as `csv` (the default), `json`
+                             or `jsonl`.
+    --rejects <path>         Records every refused input record.
+    --threads <n>            Processes the input across `n` threads.
+    --store <path>           Keeps the transactions in a file instead of
+                             memory.
+    --state <path>           Loads the engine state from a snapshot, and saves
+                             it back (except for statements and validations).
+    --journal <path>         Appends every accepted transaction onto a journal.
+    --strict                 Fails on the first refused input record.
+    --allow-redispute        Allows resolved transactions to be disputed again.
+    --dispute-withdrawals    Allows withdrawals to be disputed.
+    --dispute-policy <p>     Whether a dispute which the available funds do not
+                             cover is refused (`reject`, the default), pushes
+                             them below zero (`allow-negative`), or only holds
+                             what is available (`hold-partial`).
+
+`--strict` cannot be combined with `--threads`. `replay` does not accept
+`--rejects`, `--threads` or `--journal`, `statement` does not accept
+`--threads` or `--journal`, `validate` does not accept `--journal`, `--strict`
+or `--output-format`, and `serve` only accepts `--store`, `--journal` and the
+dispute options.";
+
+#[derive(Default, PartialEq)]
+enum Command {
+    #[default]
+    Process,
+    Replay,
+    Statement,
+    Validate,
+    Serve,
 }
 
-#[cfg(test)]
-macro_rules! client {
-    ([$client:expr, $available:expr, $held:expr, $locked:expr]) => {
-        crate::client::Client::new($client, $available, $held, $locked)
+#[derive(Default)]
+struct Args {
+    command: Command,
+    src: String,
+    input_format: Option<InputFormat>,
+    output: Option<PathBuf>,
+    rejects: Option<PathBuf>,
+    threads: Option<NonZeroUsize>,
+    store: Option<PathBuf>,
+    state: Option<PathBuf>,
+    journal: Option<PathBuf>,
+    client: Option<ClientId>,
+    output_format: Option<Format>,
+    strict: bool,
+    policy: Policy,
+}
+
+fn parse_args<I>(mut args: I) -> Option<Args>
+where
+    I: Iterator<Item = String>,
+{
+    let mut parsed = Args::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--input-format" => {
+                parsed.input_format = Some(args.next()?.parse().ok()?)
+            },
+            "--output" => parsed.output = Some(args.next()?.into()),
+            "--rejects" => parsed.rejects = Some(args.next()?.into()),
+            "--threads" => parsed.threads = Some(args.next()?.parse().ok()?),
+            "--store" => parsed.store = Some(args.next()?.into()),
+            "--state" => parsed.state = Some(args.next()?.into()),
+            "--journal" => parsed.journal = Some(args.next()?.into()),
+            "--client" => parsed.client = Some(args.next()?.parse().ok()?),
+            "--output-format" => {
+                parsed.output_format = Some(args.next()?.parse().ok()?)
+            },
+            "--strict" => parsed.strict = true,
+            "--allow-redispute" => parsed.policy.allow_redispute = true,
+            "--dispute-withdrawals" => parsed.policy.dispute_withdrawals = true,
+            "--dispute-policy" => {
+                parsed.policy.dispute_policy = args.next()?.parse().ok()?
+            },
+            "replay" | "statement" | "validate" | "serve"
+                if parsed.src.is_empty()
+                    && parsed.command == Command::Process =>
+            {
+                parsed.command = match arg.as_str() {
+                    "replay" => Command::Replay,
+                    "statement" => Command::Statement,
+                    "validate" => Command::Validate,
+                    _ => Command::Serve,
+                }
+            },
+            _ if parsed.src.is_empty() => parsed.src = arg,
+            _ => return None,
+        };
+    }
+    match is_valid(&parsed) {
+        true => Some(parsed),
+        false => None,
+    }
+}
+
+/// Whether the options of `args` are all supported by its command.
+fn is_valid(args: &Args) -> bool {
+    let Args {
+        command,
+        src,
+        input_format,
+        output,
+        rejects,
+        threads,
+        state,
+        journal,
+        client,
+        output_format,
+        strict,
+        ..
+    } = args;
+    let statement = *command == Command::Statement;
+    let supported = match command {
+        Command::Process => true,
+        Command::Replay => {
+            rejects.is_none() && threads.is_none() && journal.is_none()
+        },
+        Command::Statement => threads.is_none() && journal.is_none(),
+        Command::Validate => {
+            journal.is_none() && !strict && output_format.is_none()
+        },
+        Command::Serve => {
+            input_format.is_none()
+                && output.is_none()
+                && rejects.is_none()
+                && threads.is_none()
+                && state.is_none()
+                && output_format.is_none()
+                && !strict
+        },
     };
+    supported
+        && !src.is_empty()
+        && !(*strict && threads.is_some())
+        && client.is_some() == statement
 }
 
-#[cfg(test)]
-macro_rules! transaction {
-    (["deposit", $client:expr, $tx:expr, $amount:expr]) => {
-        crate::transaction::Transaction::Deposit(crate::transaction::Charge {
-            client: $client,
-            tx: $tx,
-            amount: $amount,
-        })
+fn main() -> ExitCode {
+    let Some(args) = parse_args(env::args().skip(1)) else {
+        eprintln!("{}", USAGE);
+        return ExitCode::FAILURE;
     };
-    (["withdrawal", $client:expr, $tx:expr, $amount:expr]) => {
-        crate::transaction::Transaction::Withdrawal(crate::transaction::Charge {
-            client: $client,
-            tx: $tx,
-            amount: $amount,
-        })
+    match run(args) {
+        Ok(exit_code) => exit_code,
+        Err(error) => {
+            eprintln!("Oops, {}.", error);
+            ExitCode::FAILURE
+        },
+    }
+}
+
+fn run(
+    Args {
+        command,
+        src,
+        input_format,
+        mut output,
+        rejects,
+        threads,
+        store,
+        state,
+        journal,
+        client,
+        output_format,
+        strict,
+        policy,
+    }: Args,
+) -> EngineResult<ExitCode> {
+    let mut engine = match store {
+        Some(store) => {
+            let store = Box::new(FileStore::create(store)?);
+            Engine::with_core(Core::with_store(policy, store))
+        },
+        None => Engine::new(policy),
     };
-    (["dispute", $client:expr, $tx:expr]) => {
-        crate::transaction::Transaction::Dispute(crate::transaction::ChargeRef {
-            client: $client,
-            tx: $tx,
-        })
+    engine.set_input_format(
+        input_format.unwrap_or_else(|| InputFormat::detect(Path::new(&src))),
+    );
+    if let Some(state) = &state {
+        restore(&mut engine, state)?;
     };
-    (["resolve", $client:expr, $tx:expr]) => {
-        crate::transaction::Transaction::Resolve(crate::transaction::ChargeRef {
-            client: $client,
-            tx: $tx,
-        })
+    if let Some(journal) = journal {
+        engine.set_journal(Journal::open(journal)?);
     };
-    (["chargeback", $client:expr, $tx:expr]) => {
-        crate::transaction::Transaction::Chargeback(crate::transaction::ChargeRef {
-            client: $client,
-            tx: $tx,
-        })
+    if command == Command::Serve {
+        let listener = TcpListener::bind(&src)?;
+        eprintln!("Listening on {}.", listener.local_addr()?);
+        engine.serve(listener)?;
+        return Ok(ExitCode::SUCCESS);
     };
-}
-
-#[cfg(test)]
-macro_rules! process {
-    ([$($transaction:expr),*$(,)?] -> $core:expr) => {
-        $($core.process($transaction).unwrap();)*
+    let mut rejects = rejects.map(serializer::rejects_writer).transpose()?;
+    let mut summary = Summary::default();
+    let mut problems = match command {
+        Command::Validate => Some(create_output(output.take())?),
+        _ => None,
+    };
+    let reject = |reject: Reject| {
+        if let Some(writer) = &mut rejects {
+            serializer::serialize_reject(writer, &reject)?;
+        };
+        if let Some(problems) = &mut problems {
+            writeln!(problems, "{}", reject)?;
+            summary.add(&reject);
+        };
+        match strict {
+            true => Err(reject.into()),
+            false => Ok(()),
+        }
+    };
+    let statement = match (command, threads, client) {
+        (Command::Statement, _, Some(client)) => {
+            let src = deserializer::open(src)?;
+            Some(engine.statement(src, client, reject)?)
+        },
+        (Command::Replay, _, _) => {
+            engine.replay(deserializer::open(src)?)?;
+            None
+        },
+        (_, Some(threads), _) => {
+            engine.process_path_parallel(src, threads, reject)?;
+            None
+        },
+        (_, None, _) => {
+            engine.process_path(src, reject)?;
+            None
+        },
     };
+    if let Some(mut writer) = rejects {
+        writer.flush()?;
+    };
+    if let Some(mut problems) = problems {
+        if !summary.is_clean() {
+            writeln!(problems)?;
+        };
+        write!(problems, "{}", summary)?;
+        problems.flush()?;
+        return match summary.is_clean() {
+            true => Ok(ExitCode::SUCCESS),
+            false => Ok(ExitCode::FAILURE),
+        };
+    };
+    let dst = create_output(output)?;
+    let format = output_format.unwrap_or_default();
+    match statement {
+        Some(entries) => {
+            serializer::serialize_statement(dst, &entries, format)?
+        },
+        None => {
+            if let Some(state) = state {
+                save(&engine, state)?;
+            };
+            engine.write_as(dst, format)?
+        },
+    };
+    Ok(ExitCode::SUCCESS)
 }
 
-pub mod client;
-pub mod engine;
-pub mod transaction;
+/// Opens the file at `output` for writing, or `stdout` if there is none.
+fn create_output(output: Option<PathBuf>) -> EngineResult<Box<dyn io::Write>> {
+    match output {
+        Some(output) => Ok(Box::new(File::create(output)?)),
+        None => Ok(Box::new(io::stdout().lock())),
+    }
+}
 
-pub mod types {
-    pub type EngineResult<T> = Result<T, &'static str>;
-    pub type ClientId = u16;
-    pub type Amount = f64;
-    pub type TransactionId = u32;
+/// Loads the snapshot at `state` into `engine`, unless there is none yet.
+fn restore(engine: &mut Engine, state: &Path) -> EngineResult<()> {
+    match File::open(state) {
+        Ok(file) => engine.restore(file),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(error) => Err(error.into()),
+    }
 }
 
-fn main() -> ExitCode {
-    let args = env::args().collect::<Vec<_>>();
-    let args = &*args;
-    match args {
-        [_, src] => engine::run(src),
-        _ => Err("Oops, this binary requires one argument (which is the relative path to the input file)."),
-    }.map_or(ExitCode::FAILURE, |()| ExitCode::SUCCESS)
+/// Saves a snapshot of `engine` at `state`.
+///
+/// The snapshot is first written aside and then moved into place, so that an
+/// interrupted save never leaves a truncated snapshot behind.
+fn save(engine: &Engine, state: PathBuf) -> EngineResult<()> {
+    let mut pending = state.clone().into_os_string();
+    pending.push(".pending");
+    let mut file = File::create(&pending)?;
+    engine.snapshot(&mut file)?;
+    file.sync_all()?;
+    fs::rename(pending, state)?;
+    Ok(())
 }
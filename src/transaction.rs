@@ -1,38 +1,125 @@
+use std::fmt;
+
 use serde::Deserialize;
 
 use crate::types::Amount;
 use crate::types::ClientId;
 use crate::types::TransactionId;
 
-#[derive(Deserialize, PartialEq)]
-#[cfg_attr(test, derive(Debug))]
+#[derive(Clone, Debug, Deserialize, PartialEq)]
 pub struct Charge {
     pub client: ClientId,
     pub tx: TransactionId,
     pub amount: Amount,
 }
 
-#[derive(Deserialize, PartialEq)]
-#[cfg_attr(test, derive(Debug))]
+/// A reference to the charge `tx`, by a dispute, resolve or chargeback of
+/// either `amount` (i.e., part of the charge), or the whole of it.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
 pub struct ChargeRef {
     pub client: ClientId,
     pub tx: TransactionId,
+    pub amount: Option<Amount>,
+}
+
+/// A move of `charge.amount` out of `charge.client` and into `to`, which is
+/// applied (and disputed) as a single unit.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct Transfer {
+    pub charge: Charge,
+    pub to: ClientId,
+}
+
+/// An administrative action onto the account of `client`, recorded under `tx`
+/// along with the code of its `reason` (e.g., `false_positive`).
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct AccountAction {
+    pub client: ClientId,
+    pub tx: TransactionId,
+    pub reason: String,
 }
 
-#[derive(Deserialize, PartialEq)]
-#[cfg_attr(test, derive(Debug))]
+#[derive(Clone, Debug, Deserialize, PartialEq)]
 pub enum Transaction {
     Deposit(Charge),
     Withdrawal(Charge),
+    Transfer(Transfer),
     Dispute(ChargeRef),
     Resolve(ChargeRef),
     Chargeback(ChargeRef),
+    Freeze(AccountAction),
+    Unfreeze(AccountAction),
+    Close(AccountAction),
+}
+
+/// The direction in which a charge moved funds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChargeKind {
+    Deposit,
+    Withdrawal,
+    /// Out of the client of the charge, and into `to`.
+    Transfer {
+        to: ClientId,
+    },
 }
 
 impl Transaction {
-    pub fn as_deposit(&self) -> Option<&Charge> {
+    pub fn client(&self) -> ClientId {
         match self {
-            Self::Deposit(charge) => Some(charge),
+            Self::Deposit(Charge { client, .. })
+            | Self::Withdrawal(Charge { client, .. })
+            | Self::Transfer(Transfer {
+                charge: Charge { client, .. },
+                ..
+            })
+            | Self::Dispute(ChargeRef { client, .. })
+            | Self::Resolve(ChargeRef { client, .. })
+            | Self::Chargeback(ChargeRef { client, .. })
+            | Self::Freeze(AccountAction { client, .. })
+            | Self::Unfreeze(AccountAction { client, .. })
+            | Self::Close(AccountAction { client, .. }) => *client,
+        }
+    }
+
+    pub fn tx(&self) -> TransactionId {
+        match self {
+            Self::Deposit(Charge { tx, .. })
+            | Self::Withdrawal(Charge { tx, .. })
+            | Self::Transfer(Transfer {
+                charge: Charge { tx, .. },
+                ..
+            })
+            | Self::Dispute(ChargeRef { tx, .. })
+            | Self::Resolve(ChargeRef { tx, .. })
+            | Self::Chargeback(ChargeRef { tx, .. })
+            | Self::Freeze(AccountAction { tx, .. })
+            | Self::Unfreeze(AccountAction { tx, .. })
+            | Self::Close(AccountAction { tx, .. }) => *tx,
+        }
+    }
+
+    /// The name of the type of `self`, as spelled in the input.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Deposit(_) => "deposit",
+            Self::Withdrawal(_) => "withdrawal",
+            Self::Transfer(_) => "transfer",
+            Self::Dispute(_) => "dispute",
+            Self::Resolve(_) => "resolve",
+            Self::Chargeback(_) => "chargeback",
+            Self::Freeze(_) => "freeze",
+            Self::Unfreeze(_) => "unfreeze",
+            Self::Close(_) => "close",
+        }
+    }
+
+    pub fn as_charge(&self) -> Option<(ChargeKind, &Charge)> {
+        match self {
+            Self::Deposit(charge) => Some((ChargeKind::Deposit, charge)),
+            Self::Withdrawal(charge) => Some((ChargeKind::Withdrawal, charge)),
+            Self::Transfer(Transfer { charge, to }) => {
+                Some((ChargeKind::Transfer { to: *to }, charge))
+            },
             _ => None,
         }
     }
@@ -40,10 +127,97 @@ impl Transaction {
     pub fn charge_tx(&self) -> Option<TransactionId> {
         match self {
             Self::Deposit(Charge { tx, .. })
-            | Self::Withdrawal(Charge { tx, .. }) => Some(*tx),
+            | Self::Withdrawal(Charge { tx, .. })
+            | Self::Transfer(Transfer {
+                charge: Charge { tx, .. },
+                ..
+            }) => Some(*tx),
             Self::Dispute(ChargeRef { .. })
             | Self::Resolve(ChargeRef { .. })
-            | Self::Chargeback(ChargeRef { .. }) => None,
+            | Self::Chargeback(ChargeRef { .. })
+            | Self::Freeze(AccountAction { .. })
+            | Self::Unfreeze(AccountAction { .. })
+            | Self::Close(AccountAction { .. }) => None,
         }
     }
+
+    /// The amount which `self` carries: that of a charge, or the optional one
+    /// of a dispute, resolve or chargeback.
+    pub fn amount(&self) -> Option<Amount> {
+        match self {
+            Self::Deposit(Charge { amount, .. })
+            | Self::Withdrawal(Charge { amount, .. })
+            | Self::Transfer(Transfer {
+                charge: Charge { amount, .. },
+                ..
+            }) => Some(*amount),
+            Self::Dispute(ChargeRef { amount, .. })
+            | Self::Resolve(ChargeRef { amount, .. })
+            | Self::Chargeback(ChargeRef { amount, .. }) => *amount,
+            Self::Freeze(_) | Self::Unfreeze(_) | Self::Close(_) => None,
+        }
+    }
+
+    /// The id of the charge which `self` refers to, if it is a dispute, a
+    /// resolve or a chargeback.
+    pub fn referenced_tx(&self) -> Option<TransactionId> {
+        match self {
+            Self::Dispute(ChargeRef { tx, .. })
+            | Self::Resolve(ChargeRef { tx, .. })
+            | Self::Chargeback(ChargeRef { tx, .. }) => Some(*tx),
+            _ => None,
+        }
+    }
+
+    /// The reason code of an administrative action.
+    pub fn reason(&self) -> Option<&str> {
+        match self {
+            Self::Freeze(AccountAction { reason, .. })
+            | Self::Unfreeze(AccountAction { reason, .. })
+            | Self::Close(AccountAction { reason, .. }) => Some(reason),
+            _ => None,
+        }
+    }
+}
+
+/// The stage of the dispute lifecycle in which a stored transaction is.
+///
+/// A transaction starts off as `Processed`. It can then be `Disputed`, after
+/// which the dispute is closed by either being `Resolved` or `ChargedBack`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DisputeState {
+    Processed,
+    Disputed,
+    Resolved,
+    ChargedBack,
+}
+
+impl DisputeState {
+    /// Whether the lifecycle permits moving from `self` to `to`.
+    ///
+    /// A `Disputed` transaction may be disputed further (for more of its
+    /// amount). A `Resolved` transaction may only be disputed again if
+    /// `redisputable` is set; a `ChargedBack` transaction is final.
+    pub fn can_transition(self, to: Self, redisputable: bool) -> bool {
+        match (self, to) {
+            (Self::Processed, Self::Disputed)
+            | (Self::Disputed, Self::Disputed)
+            | (Self::Disputed, Self::Resolved)
+            | (Self::Disputed, Self::ChargedBack) => true,
+            (Self::Resolved, Self::Disputed) => redisputable,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for DisputeState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match self {
+            Self::Processed => "processed",
+            Self::Disputed => "disputed",
+            Self::Resolved => "resolved",
+            Self::ChargedBack => "charged back",
+        };
+        f.write_str(state)
+    }
 }